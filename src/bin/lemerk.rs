@@ -0,0 +1,427 @@
+//! Command-line tool building LeMerk trees from files, and generating and verifying their proofs.
+//! Trees are stored with their binary encoding, which records the hasher they were built with.
+use std::fmt;
+use std::io::{
+    self,
+    BufRead,
+    Read,
+    Write,
+};
+use lemerk::{
+    LeMerkTree,
+    MerkleHasher,
+    builder::{
+        LeMerkBuilder,
+        depth_for_leaf_count,
+    },
+    data::{
+        DepthOffset,
+        Index,
+        OddNodePolicy,
+    },
+    error::{
+        LeMerkBuilderError,
+        LeMerkFormatError,
+        LeMerkTreeError,
+    },
+    format::TreeHeader,
+    proof::{
+        MerkleProof,
+        verify_proof,
+    },
+    traits::SizedTree,
+};
+
+const SIZE: usize = 32;
+
+const USAGE: &str = "\
+Usage:
+  lemerk build <tree> (--lines <file|-> | <file>...) [--hasher <name>] [--odd-node-policy promote|duplicate|rfc6962]
+  lemerk root <tree>
+  lemerk prove <tree> <leaf>
+  lemerk verify <proof> <leaf> (--tree <tree> | --root <hex> --leaf-count <n> --odd-node-policy <policy> (--leaf <hex> | --data <file>) [--hasher <name>])
+  lemerk update <tree> <leaf> (--leaf <hex> | --data <file>)
+
+Leaves are given by their position from 0, verify rejects a proof for another position. Build hashes every line or every file into a leaf.
+Hashers: sha3-256 (default), keccak256, and with their features sha256, rfc6962-sha256, blake2b256, blake3.";
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Io(io::Error),
+    Json(serde_json::Error),
+    Builder(LeMerkBuilderError),
+    Tree(LeMerkTreeError),
+    Format(LeMerkFormatError),
+    InvalidProof,
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::Io(error) => write!(f, "{}", error),
+            CliError::Json(error) => write!(f, "invalid proof: {}", error),
+            CliError::Builder(error) => write!(f, "can't build the tree: {}", error),
+            CliError::Tree(error) => write!(f, "tree error: {}", error),
+            CliError::Format(error) => write!(f, "invalid tree file: {}", error),
+            CliError::InvalidProof => write!(f, "invalid proof"),
+        }
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(value: io::Error) -> CliError {
+        CliError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(value: serde_json::Error) -> CliError {
+        CliError::Json(value)
+    }
+}
+
+impl From<LeMerkBuilderError> for CliError {
+    fn from(value: LeMerkBuilderError) -> CliError {
+        CliError::Builder(value)
+    }
+}
+
+impl From<LeMerkTreeError> for CliError {
+    fn from(value: LeMerkTreeError) -> CliError {
+        CliError::Tree(value)
+    }
+}
+
+impl From<LeMerkFormatError> for CliError {
+    fn from(value: LeMerkFormatError) -> CliError {
+        CliError::Format(value)
+    }
+}
+
+fn usage<T>(message: &str) -> Result<T, CliError> {
+    Err(CliError::Usage(message.to_string()))
+}
+
+/// Calls $run, generic over the hasher and the output, with the hasher selected by its name or its identifier.
+macro_rules! with_hasher {
+    ($select:expr, $run:ident($($arg:expr),*)) => {
+        match $select {
+            ("sha3-256", _) | (_, <sha3::Sha3_256 as MerkleHasher>::HASHER_ID) => $run::<sha3::Sha3_256, _>($($arg),*),
+            ("keccak256", _) | (_, <lemerk::crypto::Keccak256 as MerkleHasher>::HASHER_ID) => $run::<lemerk::crypto::Keccak256, _>($($arg),*),
+            #[cfg(feature = "sha2")]
+            ("sha256", _) | (_, <lemerk::crypto::Sha256 as MerkleHasher>::HASHER_ID) => $run::<lemerk::crypto::Sha256, _>($($arg),*),
+            #[cfg(feature = "sha2")]
+            ("rfc6962-sha256", _) | (_, <lemerk::crypto::CertificateTransparency as MerkleHasher>::HASHER_ID) => $run::<lemerk::crypto::CertificateTransparency, _>($($arg),*),
+            #[cfg(feature = "blake2")]
+            ("blake2b256", _) | (_, <lemerk::crypto::Blake2b256 as MerkleHasher>::HASHER_ID) => $run::<lemerk::crypto::Blake2b256, _>($($arg),*),
+            #[cfg(feature = "blake3")]
+            ("blake3", _) | (_, <lemerk::crypto::Blake3 as MerkleHasher>::HASHER_ID) => $run::<lemerk::crypto::Blake3, _>($($arg),*),
+            (name, 0) => usage(&format!("unknown hasher {}", name)),
+            (_, hasher_id) => usage(&format!("unsupported hasher id {:#x}", hasher_id)),
+        }
+    };
+}
+
+/// Parsed options: positional arguments, and the value of every --name option.
+struct Arguments {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Arguments {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args.next().ok_or_else(|| CliError::Usage(format!("missing value of --{}", name)))?;
+                    options.push((name.to_string(), value.clone()));
+                },
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Arguments { positional, options })
+    }
+    fn get_positional(&self, position: usize, name: &str) -> Result<&str, CliError> {
+        self.positional.get(position).map(String::as_str).ok_or_else(|| CliError::Usage(format!("missing {}", name)))
+    }
+    fn get_option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(option, _)| option == name).map(|(_, value)| value.as_str())
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let stdout = io::stdout();
+    match run(&args, &mut stdout.lock()) {
+        Ok(()) => {},
+        // The output was closed by its reader, e.g. head.
+        Err(CliError::Io(error)) if error.kind() == io::ErrorKind::BrokenPipe => {},
+        Err(error) => {
+            eprintln!("lemerk: {}", error);
+            std::process::exit(1);
+        },
+    };
+}
+
+fn run<W: Write>(args: &[String], out: &mut W) -> Result<(), CliError> {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), Arguments::parse(args)?),
+        None => return usage("missing command"),
+    };
+    match command {
+        "build" => {
+            let hasher = args.get_option("hasher").unwrap_or("sha3-256");
+            with_hasher!((hasher, 0), build(&args, out))
+        },
+        "root" | "prove" | "update" => {
+            let bytes = std::fs::read(args.get_positional(0, "tree")?)?;
+            let header = TreeHeader::from_bytes(&bytes)?;
+            with_hasher!(("", header.get_hasher_id()), run_on_tree(command, &args, &bytes, out))
+        },
+        "verify" => match args.get_option("tree") {
+            Some(tree) => {
+                let bytes = std::fs::read(tree)?;
+                let header = TreeHeader::from_bytes(&bytes)?;
+                with_hasher!(("", header.get_hasher_id()), verify_with_tree(&args, &bytes, out))
+            },
+            None => {
+                let hasher = args.get_option("hasher").unwrap_or("sha3-256");
+                with_hasher!((hasher, 0), verify(&args, out))
+            },
+        },
+        "help" | "-h" => {
+            writeln!(out, "{}", USAGE)?;
+            Ok(())
+        },
+        _ => usage(&format!("unknown command {}", command)),
+    }
+}
+
+fn build<H: MerkleHasher, W: Write>(args: &Arguments, out: &mut W) -> Result<(), CliError> {
+    let path = args.get_positional(0, "tree")?;
+    let leaf_data: Vec<Vec<u8>> = match args.get_option("lines") {
+        Some(lines) => read_input(lines)?
+            .lines()
+            .map(|line| line.map(String::into_bytes))
+            .collect::<Result<_, _>>()?,
+        None => args.positional[1..].iter().map(std::fs::read).collect::<Result<_, _>>()?,
+    };
+    if leaf_data.is_empty() { return usage("no leaves to build the tree from"); };
+    let odd_node_policy = parse_odd_node_policy(args.get_option("odd-node-policy").unwrap_or("promote"))?;
+    let tree: LeMerkTree<SIZE, H> = LeMerkBuilder::<SIZE>::new()
+        .with_leaf_data(leaf_data)
+        .with_odd_node_policy(odd_node_policy)
+        .try_build::<H>()?;
+    std::fs::write(path, tree.encode()?)?;
+    writeln!(out, "{}", to_hex(&tree.get_root_data()?))?;
+    Ok(())
+}
+
+fn run_on_tree<H: MerkleHasher, W: Write>(command: &str, args: &Arguments, bytes: &[u8], out: &mut W) -> Result<(), CliError> {
+    let mut tree: LeMerkTree<SIZE, H> = LeMerkTree::decode(bytes, true)?;
+    match command {
+        "root" => writeln!(out, "{}", to_hex(&tree.get_root_data()?))?,
+        "prove" => {
+            let index = get_leaf_index(&tree, args)?;
+            let (_, proof) = tree.generate_proof(index)?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&proof)?)?;
+        },
+        _ => {
+            let index = get_leaf_index(&tree, args)?;
+            let root = match (args.get_option("leaf"), args.get_option("data")) {
+                (Some(leaf), None) => tree.set_update_generate_proof(index, parse_block(leaf)?)?.0,
+                (None, Some(data)) => tree.set_leaf_data(index, &read_input(data).and_then(read_all)?)?,
+                _ => return usage("update takes either --leaf or --data"),
+            };
+            std::fs::write(args.get_positional(0, "tree")?, tree.encode()?)?;
+            writeln!(out, "{}", to_hex(&root))?;
+        },
+    };
+    Ok(())
+}
+
+/// Checks the proof of the leaf position against the stored tree, from its stored leaf up to its root.
+fn verify_with_tree<H: MerkleHasher, W: Write>(args: &Arguments, bytes: &[u8], out: &mut W) -> Result<(), CliError> {
+    let tree: LeMerkTree<SIZE, H> = LeMerkTree::decode(bytes, true)?;
+    let proof = read_proof(args)?;
+    let index = get_leaf_index(&tree, args)?;
+    let is_valid = proof.get_index() == index && tree.verify_proof(&proof)? == Some(tree.get_root_data()?);
+    report(is_valid, out)
+}
+
+/// Checks the proof against a root, for a leaf given by its position, and its block or its data.
+/// The leaf position, leaf count and odd node policy of the tree are trusted like the root, the proof can't tell them.
+fn verify<H: MerkleHasher, W: Write>(args: &Arguments, out: &mut W) -> Result<(), CliError> {
+    let proof = read_proof(args)?;
+    let root = parse_block(args.get_option("root").ok_or_else(|| CliError::Usage("missing --root".to_string()))?)?;
+    let leaf_count: usize = args.get_option("leaf-count")
+        .ok_or_else(|| CliError::Usage("missing --leaf-count".to_string()))?
+        .parse()
+        .map_err(|_| CliError::Usage("the leaf count is a number of leaves".to_string()))?;
+    let odd_node_policy = parse_odd_node_policy(args.get_option("odd-node-policy").ok_or_else(|| CliError::Usage("missing --odd-node-policy".to_string()))?)?;
+    let offset = parse_leaf_offset(args)?;
+    if offset >= leaf_count {
+        return usage(&format!("leaf {} is out of the {} leaves of the tree", offset, leaf_count));
+    };
+    let index = Index::try_from(DepthOffset::from((depth_for_leaf_count(leaf_count), offset))).map_err(LeMerkTreeError::from)?;
+    let is_valid = match (args.get_option("leaf"), args.get_option("data")) {
+        (Some(leaf), None) => verify_proof::<SIZE, H>(&root, &parse_block(leaf)?, index, leaf_count, odd_node_policy, &proof)?,
+        (None, Some(data)) => proof.verify_data::<H>(&root, &read_input(data).and_then(read_all)?, index, leaf_count, odd_node_policy)?,
+        _ => return usage("verify takes either --leaf or --data"),
+    };
+    report(is_valid, out)
+}
+
+fn report<W: Write>(is_valid: bool, out: &mut W) -> Result<(), CliError> {
+    if !is_valid {
+        return Err(CliError::InvalidProof);
+    };
+    writeln!(out, "valid")?;
+    Ok(())
+}
+
+fn read_proof(args: &Arguments) -> Result<MerkleProof<SIZE>, CliError> {
+    let proof = read_input(args.get_positional(0, "proof")?).and_then(read_all)?;
+    Ok(serde_json::from_slice(&proof)?)
+}
+
+fn parse_leaf_offset(args: &Arguments) -> Result<usize, CliError> {
+    args.get_positional(1, "leaf")?.parse().map_err(|_| CliError::Usage("the leaf is a position from 0".to_string()))
+}
+
+fn get_leaf_index<H: MerkleHasher>(tree: &LeMerkTree<SIZE, H>, args: &Arguments) -> Result<Index, CliError> {
+    let offset = parse_leaf_offset(args)?;
+    if offset >= tree.get_leaf_count() {
+        return usage(&format!("leaf {} is out of the {} leaves of the tree", offset, tree.get_leaf_count()));
+    };
+    Ok(Index::try_from(DepthOffset::from((tree.get_max_depth(), offset))).map_err(LeMerkTreeError::from)?)
+}
+
+/// Opens a file, or the standard input for -.
+fn read_input(path: &str) -> io::Result<Box<dyn BufRead>> {
+    Ok(match path {
+        "-" => Box::new(io::BufReader::new(io::stdin())),
+        path => Box::new(io::BufReader::new(std::fs::File::open(path)?)),
+    })
+}
+
+fn read_all(mut input: Box<dyn BufRead>) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_odd_node_policy(policy: &str) -> Result<OddNodePolicy, CliError> {
+    match policy {
+        "promote" => Ok(OddNodePolicy::Promote),
+        "duplicate" => Ok(OddNodePolicy::Duplicate),
+        "rfc6962" => Ok(OddNodePolicy::Rfc6962),
+        policy => usage(&format!("unknown odd node policy {}", policy)),
+    }
+}
+
+fn parse_block(hex: &str) -> Result<[u8; SIZE], CliError> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    let mut block = [0_u8; SIZE];
+    if hex.len() != SIZE * 2 || !hex.is_ascii() {
+        return usage(&format!("a block is {} hex digits", SIZE * 2));
+    };
+    for (byte, pair) in block.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| CliError::Usage("invalid hex".to_string()))?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| CliError::Usage(format!("invalid hex digits {}", pair)))?;
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+fn run_to_string(args: &[&str]) -> Result<String, CliError> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let mut out = Vec::new();
+    run(&args, &mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+#[cfg(test)]
+fn temporary_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("lemerk-cli-{}-{}", name, std::process::id())).to_string_lossy().into_owned()
+}
+
+#[test]
+fn build_prove_verify_update() {
+    let lines = temporary_path("lines");
+    let tree = temporary_path("tree");
+    let proof = temporary_path("proof");
+    let data = temporary_path("data");
+    std::fs::write(&lines, "alpha\nbeta\ngamma\ndelta\nepsilon\n").unwrap();
+    std::fs::write(&data, "gamma").unwrap();
+    let root = run_to_string(&["build", &tree, "--lines", &lines, "--hasher", "keccak256"]).unwrap();
+    let expected_tree: LeMerkTree<SIZE, lemerk::crypto::Keccak256> = LeMerkBuilder::<SIZE>::new()
+        .with_leaf_data(["alpha", "beta", "gamma", "delta", "epsilon"])
+        .try_build::<lemerk::crypto::Keccak256>()
+        .unwrap();
+    assert_eq!(root.trim(), to_hex(&expected_tree.get_root_data().unwrap()));
+    assert_eq!(run_to_string(&["root", &tree]).unwrap(), root);
+    std::fs::write(&proof, run_to_string(&["prove", &tree, "2"]).unwrap()).unwrap();
+    assert_eq!(run_to_string(&["verify", &proof, "2", "--tree", &tree]).unwrap(), "valid\n");
+    assert_eq!(run_to_string(&["verify", &proof, "2", "--root", root.trim(), "--leaf-count", "5", "--odd-node-policy", "promote", "--data", &data, "--hasher", "keccak256"]).unwrap(), "valid\n");
+    assert!(matches!(run_to_string(&["verify", &proof, "2", "--root", root.trim(), "--leaf-count", "5", "--odd-node-policy", "promote", "--data", &data]), Err(CliError::InvalidProof))); // Default sha3-256 hasher.
+    assert!(matches!(run_to_string(&["verify", &proof, "2", "--root", root.trim(), "--leaf-count", "5", "--odd-node-policy", "promote", "--data", &lines, "--hasher", "keccak256"]), Err(CliError::InvalidProof)));
+    assert!(matches!(run_to_string(&["verify", &proof, "2", "--root", root.trim(), "--leaf-count", "4", "--odd-node-policy", "promote", "--data", &data, "--hasher", "keccak256"]), Err(CliError::InvalidProof)));
+    assert!(matches!(run_to_string(&["verify", &proof, "2", "--root", root.trim(), "--odd-node-policy", "promote", "--data", &data, "--hasher", "keccak256"]), Err(CliError::Usage(_))));
+    assert!(matches!(run_to_string(&["verify", &proof, "2", "--root", root.trim(), "--leaf-count", "5", "--data", &data, "--hasher", "keccak256"]), Err(CliError::Usage(_))));
+    // The proof of leaf 2 isn't a proof of leaf 1, nor of leaf 3 holding no such data.
+    assert!(matches!(run_to_string(&["verify", &proof, "1", "--tree", &tree]), Err(CliError::InvalidProof)));
+    assert!(matches!(run_to_string(&["verify", &proof, "1", "--root", root.trim(), "--leaf-count", "5", "--odd-node-policy", "promote", "--data", &data, "--hasher", "keccak256"]), Err(CliError::InvalidProof)));
+    assert!(matches!(run_to_string(&["verify", &proof, "--root", root.trim(), "--leaf-count", "5", "--odd-node-policy", "promote", "--data", &data, "--hasher", "keccak256"]), Err(CliError::Usage(_))));
+    assert!(matches!(run_to_string(&["verify", &proof, "5", "--root", root.trim(), "--leaf-count", "5", "--odd-node-policy", "promote", "--data", &data, "--hasher", "keccak256"]), Err(CliError::Usage(_))));
+    std::fs::write(&proof, run_to_string(&["prove", &tree, "3"]).unwrap()).unwrap();
+    let new_root = run_to_string(&["update", &tree, "2", "--leaf", &"ab".repeat(SIZE)]).unwrap();
+    assert_ne!(new_root, root);
+    assert_eq!(run_to_string(&["root", &tree]).unwrap(), new_root);
+    // The sibling of leaf 3 was updated.
+    assert!(matches!(run_to_string(&["verify", &proof, "3", "--tree", &tree]), Err(CliError::InvalidProof)));
+    assert!(run_to_string(&["prove", &tree, "5"]).is_err());
+    for path in [lines, tree, proof, data] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn build_from_files() {
+    let files: Vec<String> = (0..3).map(|i| temporary_path(&format!("file-{}", i))).collect();
+    let tree = temporary_path("files-tree");
+    for (i, file) in files.iter().enumerate() {
+        std::fs::write(file, [i as u8; 100]).unwrap();
+    }
+    let mut args = vec!["build", &tree, "--odd-node-policy", "duplicate"];
+    args.extend(files.iter().map(String::as_str));
+    let root = run_to_string(&args).unwrap();
+    let expected_tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_leaf_data((0..3_u8).map(|i| [i; 100]))
+        .with_odd_node_policy(OddNodePolicy::Duplicate)
+        .try_build::<sha3::Sha3_256>()
+        .unwrap();
+    assert_eq!(root.trim(), to_hex(&expected_tree.get_root_data().unwrap()));
+    for path in files.iter().chain([&tree]) {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn usage_errors() {
+    assert!(matches!(run_to_string(&[]), Err(CliError::Usage(_))));
+    assert!(matches!(run_to_string(&["plant"]), Err(CliError::Usage(_))));
+    assert!(matches!(run_to_string(&["build", "tree", "--hasher", "md5"]), Err(CliError::Usage(_))));
+    assert!(matches!(run_to_string(&["root", "--tree"]), Err(CliError::Usage(_))));
+    assert!(matches!(parse_block("0x12"), Err(CliError::Usage(_))));
+    assert_eq!(parse_block(&format!("0x{}", "0f".repeat(SIZE))).unwrap(), [15_u8; SIZE]);
+}
@@ -1,20 +1,33 @@
-use sha3;
+#[cfg(test)]
 use hex_literal::hex;
+#[cfg(test)]
+use crate::error::LeMerkLevelError;
+use core::marker::PhantomData;
 use alloc::vec;
 use alloc::vec::Vec;
 use crate::{
     LeMerkTree,
     LeMerkLevel,
+    incremental::IncrementalTree,
+    storage::{
+        FlatHashTree,
+        LazyLevel,
+    },
     crypto::{
-        data_hash,
-        hash_visit
+        hash_visit,
+        payload_hash,
     },
     data::{
         Index,
         DepthOffset,
+        OddNodePolicy,
     },
-    VirtualNode,
     error::LeMerkBuilderError,
+    format::TreeSection,
+    traits::{
+        MerkleHasher,
+        NodeStore,
+    },
 };
 
 #[derive(Clone)]
@@ -23,6 +36,16 @@ pub struct LeMerkBuilder<const BLOCK_SIZE: usize>{
     max_depth: usize,
     /// An initial block data to instantiate the merkle tree.
     initial_block: [u8; BLOCK_SIZE],
+    /// Number of leaves holding data, if not the whole data layer.
+    leaf_count: Option<usize>,
+    /// Explicit leaves data, replacing the initial block.
+    leaves: Option<Vec<[u8; BLOCK_SIZE]>>,
+    /// Raw leaves payloads, hashed into leaves with the tree hasher when building.
+    leaf_data: Option<Vec<Vec<u8>>>,
+    /// Rule applied to nodes whose pair to ancestor doesn't cover any leaf.
+    odd_node_policy: OddNodePolicy,
+    /// Stores only the written blocks over per-depth defaults, instead of every block.
+    lazy_storage: bool,
     /// Parameter general validity flag.
     is_valid: Result<bool, LeMerkBuilderError>,
 }
@@ -32,6 +55,11 @@ impl<const BLOCK_SIZE: usize> Default for LeMerkBuilder<BLOCK_SIZE> {
         LeMerkBuilder {
             max_depth: 1,
             initial_block: [0_u8;BLOCK_SIZE],
+            leaf_count: None,
+            leaves: None,
+            leaf_data: None,
+            odd_node_policy: OddNodePolicy::default(),
+            lazy_storage: false,
             is_valid: Ok(true),
         }
     }
@@ -57,40 +85,227 @@ impl<const BLOCK_SIZE: usize> LeMerkBuilder<BLOCK_SIZE> {
         self.initial_block = initial_block;
         self
     }
-    pub fn try_build<D: sha3::Digest>(&self) -> Result<LeMerkTree<BLOCK_SIZE>, LeMerkBuilderError>{
-        self.clone().is_valid?;
-        let max_depth = self.max_depth;
-        let data_layer_length = 2_usize.checked_pow(max_depth as u32).ok_or(LeMerkBuilderError::BadPow)?;
+    /// Sets an arbitrary number of leaves, filled with the initial block.
+    /// The depth of the tree is inferred as the smallest one holding every leaf, taking precedence over the max depth.
+    pub fn with_leaf_count(mut self, leaf_count: usize) -> Self {
+        if leaf_count > 0 {
+            self.leaf_count = Some(leaf_count);
+        } else {
+            self.is_valid = Err(LeMerkBuilderError::LengthShouldBeGreaterThanZero);
+        };
+        self
+    }
+    /// Sets the leaves of the tree from explicit data, in order.
+    /// The depth of the tree is inferred from the number of leaves, taking precedence over the leaf count.
+    pub fn with_leaves(mut self, leaves: Vec<[u8; BLOCK_SIZE]>) -> Self {
+        if !leaves.is_empty() {
+            self.leaves = Some(leaves);
+            self.leaf_data = None;
+        } else {
+            self.is_valid = Err(LeMerkBuilderError::LengthShouldBeGreaterThanZero);
+        };
+        self
+    }
+    /// Sets the leaves of the tree from arbitrary length payloads, in order.
+    /// Payloads are hashed into leaves by the hasher the tree is built with, twice if it has no leaf domain separation.
+    pub fn with_leaf_data<I, T>(mut self, leaf_data: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let leaf_data: Vec<Vec<u8>> = leaf_data.into_iter().map(|data| data.as_ref().to_vec()).collect();
+        if !leaf_data.is_empty() {
+            self.leaf_data = Some(leaf_data);
+            self.leaves = None;
+        } else {
+            self.is_valid = Err(LeMerkBuilderError::LengthShouldBeGreaterThanZero);
+        };
+        self
+    }
+    pub fn with_odd_node_policy(mut self, odd_node_policy: OddNodePolicy) -> Self {
+        self.odd_node_policy = odd_node_policy;
+        self
+    }
+    /// Stores only the per-depth hashes of the initial block and the blocks written afterwards, so the memory of
+    /// a uniformly filled tree is proportional to its updates. Explicit leaves and partial trees still write their nodes,
+    /// the ones not covering any leaf being zeros as in the dense storage.
+    pub fn with_lazy_storage(mut self, lazy_storage: bool) -> Self {
+        self.lazy_storage = lazy_storage;
+        self
+    }
+    pub fn try_build<H: MerkleHasher>(&self) -> Result<LeMerkTree<BLOCK_SIZE, H>, LeMerkBuilderError>{
+        let lazy_storage = self.lazy_storage;
+        let initial_block = self.initial_block;
+        let stored_blocks = if lazy_storage { StoredBlocks::Uniform } else { StoredBlocks::Nothing };
+        self.try_build_into::<H, _, _>(stored_blocks, |max_depth, length, uniform| {
+            Ok(if lazy_storage && uniform {
+                FlatHashTree::Lazy(LazyLevel::try_new(uniform_defaults::<BLOCK_SIZE, H>(initial_block, max_depth))?)
+            } else if lazy_storage {
+                FlatHashTree::Lazy(LazyLevel::try_new(vec![[0_u8; BLOCK_SIZE]; max_depth + 1])?)
+            } else {
+                FlatHashTree::Dense(LeMerkLevel::from(vec![[0_u8;BLOCK_SIZE]; length]))
+            })
+        })
+    }
+    /// Builds the tree into a custom node store, which length must be the one of the whole flat hash tree, i.e. 2^(max_depth+1)-1.
+    /// Only the blocks differing from what the store already holds are written.
+    pub fn try_build_with_store<H: MerkleHasher, S: NodeStore<BLOCK_SIZE>>(&self, store: S) -> Result<LeMerkTree<BLOCK_SIZE, H, S>, LeMerkBuilderError>{
+        self.try_build_into::<H, S, _>(StoredBlocks::Nothing, |_, length, _| {
+            if store.len() != length { return Err(LeMerkBuilderError::StoreLengthMismatch { expected: length, found: store.len() }); };
+            Ok(store)
+        })
+    }
+    /// Restores a tree from a store already holding it, e.g. a reopened file, without writing to it.
+    /// The builder must describe the stored tree, its blocks aren't verified.
+    pub fn try_build_from_store<H: MerkleHasher, S: NodeStore<BLOCK_SIZE>>(&self, store: S) -> Result<LeMerkTree<BLOCK_SIZE, H, S>, LeMerkBuilderError>{
+        self.try_build_into::<H, S, _>(StoredBlocks::All, |_, length, _| {
+            if store.len() != length { return Err(LeMerkBuilderError::StoreLengthMismatch { expected: length, found: store.len() }); };
+            Ok(store)
+        })
+    }
+    /// Validates the builder, then fills the store given by new_store from the depth, the flat hash tree length,
+    /// and whether every leaf holds the initial block. Blocks already held by the store, as given by stored_blocks, aren't filled.
+    fn try_build_into<H: MerkleHasher, S: NodeStore<BLOCK_SIZE>, F: FnOnce(usize, usize, bool) -> Result<S, LeMerkBuilderError>>(&self, stored_blocks: StoredBlocks, new_store: F) -> Result<LeMerkTree<BLOCK_SIZE, H, S>, LeMerkBuilderError>{
+        self.is_valid?;
+        if H::OUTPUT_SIZE != BLOCK_SIZE { return Err(LeMerkBuilderError::HasherOutputSizeMismatch { expected: BLOCK_SIZE, found: H::OUTPUT_SIZE }); };
+        let hashed_leaf_data = self.leaf_data.as_ref().map(|leaf_data| {
+            leaf_data
+                .iter()
+                .map(|data| {
+                    let mut leaf = [0_u8; BLOCK_SIZE];
+                    payload_hash::<H, _>(data, &mut leaf);
+                    leaf
+                })
+                .collect::<Vec<_>>()
+        });
+        let leaves = hashed_leaf_data.as_deref().or(self.leaves.as_deref());
+        let leaf_count = leaves.map(<[_]>::len).or(self.leaf_count);
+        let max_depth = match leaf_count {
+            Some(leaf_count) => depth_for_leaf_count(leaf_count),
+            None => self.max_depth,
+        };
+        let data_layer_length = 2_usize.checked_pow(max_depth as u32).ok_or(LeMerkBuilderError::BadPow(max_depth))?;
+        let leaf_count = leaf_count.unwrap_or(data_layer_length);
         let hash_tree_data_length: Index = Index::try_from(DepthOffset::from((max_depth+1,0)))?;
         let max_index: Index = Index::from(hash_tree_data_length.get_index() - 1);
-        let mut hash_tree_data: Vec<[u8; BLOCK_SIZE]> = vec![[0_u8;BLOCK_SIZE]; hash_tree_data_length.get_index() ];
-        let mut depth_index = max_depth + 1;
-        let mut allocating_block_buffer = self.initial_block; 
-        let mut initial_index = 0;
-        while depth_index > 0 {
-            depth_index -= 1;
-            let allocation_size = 2_usize.checked_pow(depth_index as u32).ok_or(LeMerkBuilderError::BadPow)?;
-            let allocating_block = allocating_block_buffer;
-            let _: Vec<_> = (0..allocation_size)
-                .map(|i| { 
-                    hash_tree_data[i + initial_index]= allocating_block;
-                })
-                .collect();
-            initial_index += allocation_size;
-            hash_visit::<D>(&allocating_block, &allocating_block, &mut allocating_block_buffer);
+        let uniform = leaf_count == data_layer_length && leaves.is_none();
+        let mut flat_hash_tree = new_store(max_depth, hash_tree_data_length.get_index(), uniform)?;
+        if let Some(found) = flat_hash_tree.get_tree_section() {
+            let expected = TreeSection::new(leaf_count, self.odd_node_policy);
+            if found != expected { return Err(LeMerkBuilderError::StoreTreeSectionMismatch { expected, found }); };
+        };
+        if stored_blocks != StoredBlocks::All && !uniform {
+            match leaves {
+                Some(leaves) => fill_flat_hash_tree::<BLOCK_SIZE, H, S>(&mut flat_hash_tree, max_depth, leaves, self.odd_node_policy)?,
+                None => fill_flat_hash_tree::<BLOCK_SIZE, H, S>(&mut flat_hash_tree, max_depth, &vec![self.initial_block; leaf_count], self.odd_node_policy)?,
+            };
+        } else if stored_blocks == StoredBlocks::Nothing {
+            let mut depth_index = max_depth + 1;
+            let mut allocating_block_buffer = self.initial_block; 
+            let mut initial_index = 0;
+            while depth_index > 0 {
+                depth_index -= 1;
+                let allocation_size = 2_usize.checked_pow(depth_index as u32).ok_or(LeMerkBuilderError::BadPow(depth_index))?;
+                let allocating_block = allocating_block_buffer;
+                put_changed_blocks(&mut flat_hash_tree, initial_index, core::iter::repeat_n(allocating_block, allocation_size))?;
+                initial_index += allocation_size;
+                hash_visit::<H, _>(&allocating_block, &allocating_block, &mut allocating_block_buffer);
+            };
         };
-        let flat_hash_tree: LeMerkLevel<BLOCK_SIZE> = LeMerkLevel::from(hash_tree_data);
         Ok(
             LeMerkTree {
                 max_depth,
                 max_index,
                 flat_hash_tree,
                 data_layer_length,
+                leaf_count,
+                odd_node_policy: self.odd_node_policy,
+                hasher: PhantomData,
             }
         )
     }
 }
 
+impl<const BLOCK_SIZE: usize> FromIterator<[u8; BLOCK_SIZE]> for LeMerkBuilder<BLOCK_SIZE> {
+    fn from_iter<I: IntoIterator<Item = [u8; BLOCK_SIZE]>>(iter: I) -> Self {
+        Self::new().with_leaves(iter.into_iter().collect())
+    }
+}
+
+impl<const BLOCK_SIZE: usize> LeMerkBuilder<BLOCK_SIZE> {
+    /// Builds an empty append-only tree of max depth, whose positions not appended yet hold the initial block.
+    pub fn try_build_incremental<H: MerkleHasher>(&self) -> Result<IncrementalTree<BLOCK_SIZE, H>, LeMerkBuilderError> {
+        self.is_valid?;
+        if H::OUTPUT_SIZE != BLOCK_SIZE { return Err(LeMerkBuilderError::HasherOutputSizeMismatch { expected: BLOCK_SIZE, found: H::OUTPUT_SIZE }); };
+        2_usize.checked_pow(self.max_depth as u32).ok_or(LeMerkBuilderError::BadPow(self.max_depth))?;
+        Ok(IncrementalTree::new(self.max_depth, self.initial_block))
+    }
+}
+
+/// Smallest depth whose data layer holds leaf_count leaves.
+pub fn depth_for_leaf_count(leaf_count: usize) -> usize {
+    if leaf_count <= 1 { 0 } else { (leaf_count - 1).ilog2() as usize + 1 }
+}
+
+/// Hashes of a uniformly filled tree by depth, from the root to the leaves holding the initial block.
+fn uniform_defaults<const BLOCK_SIZE: usize, H: MerkleHasher>(initial_block: [u8; BLOCK_SIZE], max_depth: usize) -> Vec<[u8; BLOCK_SIZE]> {
+    let mut defaults = vec![initial_block; max_depth + 1];
+    for depth in (0..max_depth).rev() {
+        let successor = defaults[depth + 1];
+        hash_visit::<H, _>(&successor, &successor, &mut defaults[depth]);
+    }
+    defaults
+}
+
+/// Blocks a node store holds before a tree is built into it.
+#[derive(PartialEq)]
+enum StoredBlocks {
+    Nothing,
+    /// The per-depth defaults of the initial block for a uniform tree, zeros otherwise, i.e. a lazy storage.
+    Uniform,
+    All,
+}
+
+/// Fills the flat hash tree bottom-up from the leaves, hashing each level into the next one with the odd node policy.
+/// Every level starts at its flat hash tree offset, nodes that don't cover any leaf are left untouched,
+/// as well as nodes already holding their block, so a lazy storage only keeps the blocks differing from its defaults.
+fn fill_flat_hash_tree<const BLOCK_SIZE: usize, H: MerkleHasher, S: NodeStore<BLOCK_SIZE>>(flat_hash_tree: &mut S, max_depth: usize, leaves: &[[u8; BLOCK_SIZE]], odd_node_policy: OddNodePolicy) -> Result<(), LeMerkBuilderError> {
+    put_changed_blocks(flat_hash_tree, 0, leaves.iter().copied())?;
+    let mut level = LeMerkLevel::<BLOCK_SIZE, H>::next_of_blocks(leaves, odd_node_policy);
+    let mut depth_index = max_depth;
+    let mut initial_index = 2_usize.checked_pow(max_depth as u32).ok_or(LeMerkBuilderError::BadPow(max_depth))?;
+    while let Some(current_level) = level {
+        depth_index = depth_index.checked_sub(1).ok_or(LeMerkBuilderError::BadSubstraction(depth_index))?;
+        put_changed_blocks(flat_hash_tree, initial_index, current_level.0.iter().copied())?;
+        initial_index += 2_usize.checked_pow(depth_index as u32).ok_or(LeMerkBuilderError::BadPow(depth_index))?;
+        level = current_level.next_with_policy(odd_node_policy);
+    };
+    Ok(())
+}
+
+/// Number of blocks written at once by put_changed_blocks, e.g. flushed together by a file store.
+const PUT_BATCH_LENGTH: usize = 1024;
+
+/// Writes the blocks of a level from its flat hash tree offset, skipping the ones the store already holds.
+/// Blocks are put in batches of PUT_BATCH_LENGTH, so a store persisting every write doesn't do it block by block.
+fn put_changed_blocks<const BLOCK_SIZE: usize, S: NodeStore<BLOCK_SIZE>, I: IntoIterator<Item = [u8; BLOCK_SIZE]>>(flat_hash_tree: &mut S, initial_index: usize, blocks: I) -> Result<(), LeMerkBuilderError> {
+    let mut batch = Vec::new();
+    for (offset, block) in blocks.into_iter().enumerate() {
+        let index = Index::from(initial_index + offset);
+        if flat_hash_tree.get(index)? != block {
+            batch.push((index, block));
+        };
+        if batch.len() == PUT_BATCH_LENGTH {
+            flat_hash_tree.put_batch(&batch)?;
+            batch.clear();
+        };
+    }
+    if !batch.is_empty() {
+        flat_hash_tree.put_batch(&batch)?;
+    };
+    Ok(())
+}
+
 #[test]
 fn hex_representation() {
     let hex: [u8;32] = hex!("abababababababababababababababababababababababababababababababab");
@@ -124,8 +339,11 @@ fn build_zero_merkletree() {
         LeMerkTree::<SIZE> {
             max_depth: 0,
             max_index: Index::from(0),
-            flat_hash_tree: LeMerkLevel::<SIZE>::from([[0_u8;SIZE]].to_vec()),
+            flat_hash_tree: FlatHashTree::Dense(LeMerkLevel::<SIZE>::from([[0_u8;SIZE]].to_vec())),
             data_layer_length: 1,
+            leaf_count: 1,
+            odd_node_policy: OddNodePolicy::Promote,
+            hasher: PhantomData,
         }
     );
 }
@@ -144,14 +362,17 @@ fn build_initial_value_merkletree() {
         LeMerkTree::<SIZE> {
             max_depth: 1,
             max_index: Index::from(2),
-            flat_hash_tree: LeMerkLevel::<SIZE>::from(
+            flat_hash_tree: FlatHashTree::Dense(LeMerkLevel::<SIZE>::from(
                 [
                     [171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171], 
                     [171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171, 171], 
                     [105, 159, 201, 79, 241, 236, 131, 241, 171, 245, 49, 3, 14, 50, 64, 3, 231, 117, 130, 152, 40, 22, 69, 36, 95, 124, 105, 132, 37, 165, 224, 231]]
                 .to_vec()
-            ),
+            )),
             data_layer_length: 2,
+            leaf_count: 2,
+            odd_node_policy: OddNodePolicy::Promote,
+            hasher: PhantomData,
         }
     );
     assert_eq!(
@@ -173,4 +394,260 @@ fn build_merkletree_depth_20() {
         tree.get_root_data().unwrap(),
         hex!("d4490f4d374ca8a44685fe9471c5b8dbe58cdffd13d30d9aba15dd29efb92930"), 
     );
-}
\ No newline at end of file
+}
+
+#[test]
+#[should_panic(expected = "HasherOutputSizeMismatch")]
+fn build_with_mismatched_hasher_output_size_should_fail() {
+    const SIZE: usize = 32;
+    let builder: LeMerkBuilder<SIZE> = LeMerkBuilder::<SIZE>::new();
+    let _tree = builder
+        .with_max_depth(3)
+        .try_build::<sha3::Sha3_512>()
+        .unwrap();
+}
+
+#[cfg(all(feature = "sha2", feature = "blake2", feature = "blake3"))]
+#[test]
+fn build_with_feature_hashers() {
+    use crate::crypto::{Sha256, Keccak256, Blake2b256, Blake3};
+    const SIZE: usize = 32;
+    let builder: LeMerkBuilder<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(1)
+        .with_initial_block([1_u8;SIZE]);
+    assert_eq!(
+        builder.try_build::<Sha256>().unwrap().get_root_data().unwrap(),
+        hex!("7c8975e1e60a5c8337f28edf8c33c3b180360b7279644a9bc1af3c51e6220bf5"),
+    );
+    assert_eq!(
+        builder.try_build::<Keccak256>().unwrap().get_root_data().unwrap(),
+        hex!("401617bc4f769381f86be40df0207a0a3e31ae0839497a5ac6d4252dfc35577f"),
+    );
+    assert_eq!(
+        builder.try_build::<Blake2b256>().unwrap().get_root_data().unwrap(),
+        hex!("c111b5c0432d505f1eeffc7bc8e38235f0ea0146d7ac506ed11bbe2e8dd844e3"),
+    );
+    assert_eq!(
+        builder.try_build::<Blake3>().unwrap().get_root_data().unwrap(),
+        hex!("29c04cc4ce7b05d941850b358b7dfd7e6285cc54e63e2e239804c1781c3d2497"),
+    );
+}
+
+#[test]
+fn build_with_leaf_count_infers_depth() {
+    const SIZE: usize = 32;
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(3)
+        .with_leaf_count(1000)
+        .with_initial_block(hex!("abababababababababababababababababababababababababababababababab"))
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    assert_eq!(tree.max_depth, 10);
+    assert_eq!(tree.get_data_layer_length(), 1024);
+    assert_eq!(tree.get_leaf_count(), 1000);
+    assert_eq!(tree.get_leaves_indexes().len(), 1000);
+    let full_tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_leaf_count(1024)
+        .with_initial_block(hex!("abababababababababababababababababababababababababababababababab"))
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    assert_eq!(full_tree.max_depth, 10);
+    assert_ne!(tree.get_root_data().unwrap(), full_tree.get_root_data().unwrap());
+}
+
+#[test]
+#[should_panic(expected = "LengthShouldBeGreaterThanZero")]
+fn build_with_zero_leaves_should_fail() {
+    const SIZE: usize = 32;
+    let _tree = LeMerkBuilder::<SIZE>::new()
+        .with_leaf_count(0)
+        .try_build::<sha3::Sha3_256>()
+        .unwrap();
+}
+
+#[test]
+fn build_with_leaves_matches_updated_tree() {
+    const SIZE: usize = 32;
+    for odd_node_policy in [OddNodePolicy::Promote, OddNodePolicy::Duplicate] {
+        for leaf_count in [1, 2, 5, 8, 13] {
+            let leaves: Vec<[u8; SIZE]> = (0..leaf_count).map(|offset| [offset as u8; SIZE]).collect();
+            let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+                .with_leaves(leaves.clone())
+                .with_odd_node_policy(odd_node_policy)
+                .try_build::<sha3::Sha3_256>()
+                .expect("Unexpected build.");
+            let mut updated_tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+                .with_leaf_count(leaf_count)
+                .with_odd_node_policy(odd_node_policy)
+                .try_build::<sha3::Sha3_256>()
+                .expect("Unexpected build.");
+            for (leaf_index, leaf) in updated_tree.get_leaves_indexes().into_iter().zip(leaves.iter()) {
+                updated_tree.set_and_update(leaf_index, *leaf).unwrap();
+            }
+            assert_eq!(tree.get_leaf_count(), leaf_count);
+            assert_eq!(tree, updated_tree);
+        }
+    }
+}
+
+#[test]
+fn build_from_iterator_of_leaves() {
+    const SIZE: usize = 32;
+    let tree: LeMerkTree<SIZE> = (0..4_u8)
+        .map(|offset| [offset; SIZE])
+        .collect::<LeMerkBuilder<SIZE>>()
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    assert_eq!(tree.max_depth, 2);
+    let mut level: LeMerkLevel<SIZE> = LeMerkLevel::from((0..4_u8).map(|offset| [offset; SIZE]).collect::<Vec<_>>());
+    let root = level.next().and_then(|mut level| level.next()).unwrap();
+    assert_eq!(tree.get_root_data().unwrap(), root.get_cipher_block(Index::from(0)).unwrap());
+}
+
+#[test]
+#[should_panic(expected = "LengthShouldBeGreaterThanZero")]
+fn build_with_no_leaves_should_fail() {
+    const SIZE: usize = 32;
+    let _tree = LeMerkBuilder::<SIZE>::new()
+        .with_leaves(Vec::new())
+        .try_build::<sha3::Sha3_256>()
+        .unwrap();
+}
+
+#[test]
+fn build_with_leaf_data_hashes_payloads() {
+    const SIZE: usize = 32;
+    let payloads: [&[u8]; 3] = [b"first record", b"a second, longer record", b""];
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_leaf_data(payloads)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    let leaves: Vec<[u8; SIZE]> = payloads
+        .iter()
+        .map(|payload| {
+            let mut leaf = [0_u8; SIZE];
+            payload_hash::<sha3::Sha3_256, _>(payload, &mut leaf);
+            leaf
+        })
+        .collect();
+    let hashed_tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_leaves(leaves)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    assert_eq!(tree, hashed_tree);
+}
+
+#[test]
+fn payloads_are_not_node_preimages() {
+    const SIZE: usize = 32;
+    let leaves: Vec<[u8; SIZE]> = (0..4_u8).map(|i| [i; SIZE]).collect();
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_leaves(leaves.clone())
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    // Each payload is the left || right preimage of a node above the leaves.
+    let payloads: Vec<Vec<u8>> = leaves.chunks(2).map(|pair| pair.concat()).collect();
+    assert_eq!(payloads[0].len(), 64);
+    let mut node = [0_u8; SIZE];
+    let mut leaf = [0_u8; SIZE];
+    hash_visit::<sha3::Sha3_256, _>(&leaves[0], &leaves[1], &mut node);
+    payload_hash::<sha3::Sha3_256, _>(&payloads[0], &mut leaf);
+    assert_ne!(leaf, node);
+    let mut payload_tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_leaf_data(&payloads)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    assert_ne!(payload_tree.get_root_data().unwrap(), tree.get_root_data().unwrap());
+    let (root, proof) = payload_tree.generate_proof(Index::from(1)).unwrap();
+    assert!(proof.verify_data::<sha3::Sha3_256>(&root, &payloads[0], Index::from(1), 2, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &node, Index::from(1), 2, OddNodePolicy::Promote).unwrap());
+}
+
+#[cfg(test)]
+struct BatchCountingStore {
+    level: LeMerkLevel<32>,
+    batches: usize,
+}
+
+#[cfg(test)]
+impl NodeStore<32> for BatchCountingStore {
+    fn len(&self) -> usize {
+        NodeStore::len(&self.level)
+    }
+    fn get(&self, index: Index) -> Result<[u8; 32], crate::error::LeMerkLevelError> {
+        self.level.get(index)
+    }
+    fn put(&mut self, _index: Index, _block: [u8; 32]) -> Result<(), crate::error::LeMerkLevelError> {
+        panic!("the builder should only put batches");
+    }
+    fn put_batch(&mut self, blocks: &[(Index, [u8; 32])]) -> Result<(), crate::error::LeMerkLevelError> {
+        self.batches += 1;
+        self.level.put_batch(blocks)
+    }
+}
+
+#[test]
+fn build_puts_blocks_in_batches() {
+    const SIZE: usize = 32;
+    for builder in [LeMerkBuilder::<SIZE>::new().with_max_depth(11).with_initial_block([1_u8; SIZE]), LeMerkBuilder::<SIZE>::new().with_leaf_count(2000).with_initial_block([1_u8; SIZE])] {
+        let tree: LeMerkTree<SIZE> = builder.try_build::<sha3::Sha3_256>().unwrap();
+        let store = BatchCountingStore { level: LeMerkLevel::from(vec![[0_u8; SIZE]; 4095]), batches: 0 };
+        let mut store_tree = builder.try_build_with_store::<sha3::Sha3_256, _>(store).unwrap();
+        assert_eq!(store_tree.get_root_data().unwrap(), tree.get_root_data().unwrap());
+        // Two batches for the leaves, one per upper level.
+        assert_eq!(store_tree.get_flat_hash_tree_mut().batches, 2 + 11);
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+struct MapStore {
+    length: usize,
+    blocks: alloc::collections::BTreeMap<usize, [u8; 32]>,
+}
+
+#[cfg(test)]
+impl NodeStore<32> for MapStore {
+    fn len(&self) -> usize {
+        self.length
+    }
+    fn get(&self, index: Index) -> Result<[u8; 32], LeMerkLevelError> {
+        if index.get_index() >= self.length { return Err(LeMerkLevelError::Overflow { index: index.get_index(), length: self.length }); };
+        Ok(self.blocks.get(&index.get_index()).copied().unwrap_or([0_u8; 32]))
+    }
+    fn put(&mut self, index: Index, block: [u8; 32]) -> Result<(), LeMerkLevelError> {
+        if index.get_index() >= self.length { return Err(LeMerkLevelError::Overflow { index: index.get_index(), length: self.length }); };
+        self.blocks.insert(index.get_index(), block);
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_store_matches_default_storage() {
+    const SIZE: usize = 32;
+    for leaf_count in [6, 8] {
+        let builder = LeMerkBuilder::<SIZE>::new()
+            .with_leaf_count(leaf_count)
+            .with_initial_block(hex!("abababababababababababababababababababababababababababababababab"));
+        let mut tree: LeMerkTree<SIZE> = builder.try_build::<sha3::Sha3_256>().unwrap();
+        let store = MapStore { length: 15, blocks: alloc::collections::BTreeMap::new() };
+        let mut store_tree: LeMerkTree<SIZE, sha3::Sha3_256, MapStore> = builder.try_build_with_store::<sha3::Sha3_256, _>(store).unwrap();
+        assert_eq!(store_tree.get_root_data().unwrap(), tree.get_root_data().unwrap());
+        let updates: Vec<(Index, [u8; SIZE])> = tree.get_leaves_indexes().into_iter().step_by(2).map(|index| (index, [index.get_index() as u8; SIZE])).collect();
+        assert_eq!(store_tree.set_many(&updates).unwrap(), tree.set_many(&updates).unwrap());
+        for leaf_index in tree.get_leaves_indexes() {
+            assert_eq!(store_tree.generate_proof(leaf_index).unwrap(), tree.generate_proof(leaf_index).unwrap());
+        }
+        assert_eq!(store_tree.get_level_by_depth_index(1).unwrap(), tree.get_level_by_depth_index(1).unwrap());
+    }
+}
+
+#[test]
+fn custom_store_of_wrong_length_should_fail() {
+    const SIZE: usize = 32;
+    let store = MapStore { length: 7, blocks: alloc::collections::BTreeMap::new() };
+    let tree = LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(3)
+        .try_build_with_store::<sha3::Sha3_256, _>(store);
+    assert!(matches!(tree, Err(LeMerkBuilderError::StoreLengthMismatch { expected: 15, found: 7 })));
+}
@@ -1,25 +1,151 @@
 use sha3::Digest;
+use sha3::digest::{
+    OutputSizeUser,
+    typenum::Unsigned,
+};
+use core::marker::PhantomData;
+use crate::traits::MerkleHasher;
 
-/// Copies the hash of data to output.
-pub fn data_hash<D: Digest>(data: &[u8], output: &mut [u8]) {
-    let mut hasher = D::new();
-    hasher.update(data);
-    output.copy_from_slice(&hasher.finalize());
+/// Copies the hash of data to output, returns false and leaves output untouched if H::OUTPUT_SIZE isn't N.
+pub fn data_hash<H: MerkleHasher, const N: usize>(data: &[u8], output: &mut [u8; N]) -> bool {
+    if H::OUTPUT_SIZE != N { return false; };
+    H::hash_leaf(data, output);
+    true
+}
+
+/// Copies the hash of concatenated left || right to output, returns false and leaves output untouched if H::OUTPUT_SIZE isn't N.
+pub fn hash_visit<H: MerkleHasher, const N: usize>(left: &[u8], right: &[u8], output: &mut [u8; N]) -> bool {
+    if H::OUTPUT_SIZE != N { return false; };
+    H::hash_node(left, right, output);
+    true
+}
+
+/// Copies the leaf of an arbitrary length payload to output, returns false and leaves output untouched if H::OUTPUT_SIZE isn't N.
+/// Without H::LEAF_DOMAIN_SEPARATION the payload is hashed twice, so the preimage of the leaf is one block long
+/// and never the pair of blocks of a node.
+pub fn payload_hash<H: MerkleHasher, const N: usize>(data: &[u8], output: &mut [u8; N]) -> bool {
+    if !data_hash::<H, N>(data, output) { return false; };
+    if !H::LEAF_DOMAIN_SEPARATION {
+        let digest = *output;
+        H::hash_leaf(&digest, output);
+    };
+    true
 }
 
-/// Copies the hash of concatenated left || right to output.
-pub fn hash_visit<D: Digest>(left: &[u8], right: &[u8], output: &mut [u8]) {
+/// Copies the digest of the concatenated chunks to output.
+/// An output of another length than the digest is left untouched rather than truncated, padded or panicked on.
+fn digest_into<D: Digest>(chunks: &[&[u8]], output: &mut [u8]) {
     let mut hasher = D::new();
-    hasher.update(left);
-    hasher.update(right);
-    output.copy_from_slice(&hasher.finalize());
+    for chunk in chunks {
+        hasher.update(chunk);
+    }
+    let digest = hasher.finalize();
+    if output.len() == digest.len() {
+        output.copy_from_slice(&digest);
+    }
+}
+
+/// Implements MerkleHasher for RustCrypto digests with their hasher identifier, leaves are H(data) and nodes are H(left || right).
+macro_rules! impl_digest_hasher {
+    ($($digest:ty => $hasher_id:expr),* $(,)?) => {
+        $(
+            impl MerkleHasher for $digest {
+                const OUTPUT_SIZE: usize = <<$digest as OutputSizeUser>::OutputSize as Unsigned>::USIZE;
+                const HASHER_ID: u32 = $hasher_id;
+                fn hash_leaf(data: &[u8], output: &mut [u8]) {
+                    digest_into::<$digest>(&[data], output);
+                }
+                fn hash_node(left: &[u8], right: &[u8], output: &mut [u8]) {
+                    digest_into::<$digest>(&[left, right], output);
+                }
+            }
+        )*
+    };
+}
+
+impl_digest_hasher!(
+    sha3::Sha3_224 => 0x01,
+    sha3::Sha3_256 => 0x02,
+    sha3::Sha3_384 => 0x03,
+    sha3::Sha3_512 => 0x04,
+    sha3::Keccak256 => 0x05,
+);
+
+/// RFC 6962 (Certificate Transparency) domain separation over a digest D.
+/// Leaves are hashed as D(0x00 || data) and nodes as D(0x01 || left || right),
+/// so a leaf can never be confused with an internal node.
+pub struct Rfc6962<D>(PhantomData<D>);
+
+/// Prefix of the leaf hashes in RFC 6962.
+pub const RFC6962_LEAF_PREFIX: u8 = 0x00;
+/// Prefix of the node hashes in RFC 6962.
+pub const RFC6962_NODE_PREFIX: u8 = 0x01;
+/// Flag of the RFC 6962 hasher identifiers, set over the identifier of the wrapped digest.
+pub const RFC6962_HASHER_ID_FLAG: u32 = 0x100;
+
+impl<D: Digest + MerkleHasher> MerkleHasher for Rfc6962<D> {
+    const OUTPUT_SIZE: usize = <<D as OutputSizeUser>::OutputSize as Unsigned>::USIZE;
+    const HASHER_ID: u32 = RFC6962_HASHER_ID_FLAG | D::HASHER_ID;
+    const LEAF_DOMAIN_SEPARATION: bool = true;
+    fn hash_leaf(data: &[u8], output: &mut [u8]) {
+        digest_into::<D>(&[&[RFC6962_LEAF_PREFIX], data], output);
+    }
+    fn hash_node(left: &[u8], right: &[u8], output: &mut [u8]) {
+        digest_into::<D>(&[&[RFC6962_NODE_PREFIX], left, right], output);
+    }
+}
+
+/// The Certificate Transparency tree hasher, RFC 6962 over SHA-256.
+#[cfg(feature = "sha2")]
+pub type CertificateTransparency = Rfc6962<sha2::Sha256>;
+
+/// Keccak-256, as used by Ethereum.
+pub type Keccak256 = sha3::Keccak256;
+
+/// SHA-256, as used by Bitcoin and Certificate Transparency.
+#[cfg(feature = "sha2")]
+pub type Sha256 = sha2::Sha256;
+
+#[cfg(feature = "sha2")]
+impl_digest_hasher!(sha2::Sha256 => 0x06);
+
+/// BLAKE2b with a 256 bits output.
+#[cfg(feature = "blake2")]
+pub type Blake2b256 = blake2::Blake2b<blake2::digest::consts::U32>;
+
+#[cfg(feature = "blake2")]
+impl_digest_hasher!(Blake2b256 => 0x07);
+
+/// BLAKE3 with its default 256 bits output.
+#[cfg(feature = "blake3")]
+pub type Blake3 = blake3::Hasher;
+
+#[cfg(feature = "blake3")]
+impl MerkleHasher for blake3::Hasher {
+    const OUTPUT_SIZE: usize = blake3::OUT_LEN;
+    const HASHER_ID: u32 = 0x08;
+    fn hash_leaf(data: &[u8], output: &mut [u8]) {
+        let digest = blake3::hash(data);
+        if output.len() == blake3::OUT_LEN {
+            output.copy_from_slice(digest.as_bytes());
+        }
+    }
+    fn hash_node(left: &[u8], right: &[u8], output: &mut [u8]) {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(left);
+        hasher.update(right);
+        let digest = hasher.finalize();
+        if output.len() == blake3::OUT_LEN {
+            output.copy_from_slice(digest.as_bytes());
+        }
+    }
 }
 
 #[test]
 fn test_data_hash() {
     let value = "hello world".as_bytes();
     let mut buffer = [0_u8;32];
-    data_hash::<sha3::Sha3_256>(value, &mut buffer);
+    data_hash::<sha3::Sha3_256, _>(value, &mut buffer);
     assert_eq!(buffer,[100, 75, 204, 126, 86, 67, 115, 4, 9, 153, 170, 200, 158, 118, 34, 243, 202, 113, 251, 161, 217, 114, 253, 148, 163, 28, 59, 251, 242, 78, 57, 56]);
 }
 
@@ -27,7 +153,7 @@ fn test_data_hash() {
 fn test_preconcatenated_data_hash() {
     let value = [1_u8;64];
     let mut buffer = [0_u8;32];
-    data_hash::<sha3::Sha3_256>(&value, &mut buffer);
+    data_hash::<sha3::Sha3_256, _>(&value, &mut buffer);
     assert_eq!(buffer,[128, 53, 242, 62, 238, 50, 183, 173, 214, 12, 161, 141, 29, 27, 75, 79, 147, 94, 241, 23, 108, 49, 146, 107, 179, 81, 183, 109, 129, 9, 133, 244]);
 }
 
@@ -35,7 +161,206 @@ fn test_preconcatenated_data_hash() {
 fn test_hash_visit() {
     let initial_value = [1_u8;32];
     let mut buffer = [0_u8;32];
-    hash_visit::<sha3::Sha3_256>(&initial_value, &initial_value, &mut buffer);
+    hash_visit::<sha3::Sha3_256, _>(&initial_value, &initial_value, &mut buffer);
 
     assert_eq!(buffer,[128, 53, 242, 62, 238, 50, 183, 173, 214, 12, 161, 141, 29, 27, 75, 79, 147, 94, 241, 23, 108, 49, 146, 107, 179, 81, 183, 109, 129, 9, 133, 244]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_output_size_mismatch_is_rejected() {
+    let mut short = [7_u8;28];
+    assert!(!data_hash::<sha3::Sha3_256, _>("hello world".as_bytes(), &mut short));
+    assert!(!hash_visit::<sha3::Sha3_256, _>(&[1_u8;32], &[1_u8;32], &mut short));
+    assert_eq!(short, [7_u8;28]);
+    let mut long = [7_u8;64];
+    <sha3::Sha3_256 as MerkleHasher>::hash_leaf("hello world".as_bytes(), &mut long);
+    <sha3::Sha3_256 as MerkleHasher>::hash_node(&[1_u8;32], &[1_u8;32], &mut long);
+    assert_eq!(long, [7_u8;64]);
+}
+
+#[test]
+fn test_output_size() {
+    assert_eq!(<sha3::Sha3_224 as MerkleHasher>::OUTPUT_SIZE, 28);
+    assert_eq!(<sha3::Sha3_256 as MerkleHasher>::OUTPUT_SIZE, 32);
+    assert_eq!(<sha3::Sha3_512 as MerkleHasher>::OUTPUT_SIZE, 64);
+}
+
+#[test]
+fn test_hasher_ids_are_distinct() {
+    let hasher_ids = [
+        <sha3::Sha3_224 as MerkleHasher>::HASHER_ID,
+        <sha3::Sha3_256 as MerkleHasher>::HASHER_ID,
+        <sha3::Sha3_384 as MerkleHasher>::HASHER_ID,
+        <sha3::Sha3_512 as MerkleHasher>::HASHER_ID,
+        <Keccak256 as MerkleHasher>::HASHER_ID,
+        <Rfc6962<sha3::Sha3_256> as MerkleHasher>::HASHER_ID,
+        #[cfg(feature = "sha2")]
+        <Sha256 as MerkleHasher>::HASHER_ID,
+        #[cfg(feature = "sha2")]
+        <CertificateTransparency as MerkleHasher>::HASHER_ID,
+        #[cfg(feature = "blake2")]
+        <Blake2b256 as MerkleHasher>::HASHER_ID,
+        #[cfg(feature = "blake3")]
+        <Blake3 as MerkleHasher>::HASHER_ID,
+    ];
+    for (i, hasher_id) in hasher_ids.iter().enumerate() {
+        assert!(*hasher_id != 0 && *hasher_id <= 0x1ff);
+        assert!(!hasher_ids[i + 1..].contains(hasher_id));
+    }
+}
+
+#[cfg(test)]
+use hex_literal::hex;
+
+#[test]
+fn test_keccak256_vectors() {
+    let mut buffer = [0_u8;32];
+    data_hash::<Keccak256, _>("hello world".as_bytes(), &mut buffer);
+    assert_eq!(buffer, hex!("47173285a8d7341e5e972fc677286384f802f8ef42a5ec5f03bbfa254cb01fad"));
+    hash_visit::<Keccak256, _>(&[1_u8;32], &[1_u8;32], &mut buffer);
+    assert_eq!(buffer, hex!("401617bc4f769381f86be40df0207a0a3e31ae0839497a5ac6d4252dfc35577f"));
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn test_sha256_vectors() {
+    let mut buffer = [0_u8;32];
+    data_hash::<Sha256, _>("hello world".as_bytes(), &mut buffer);
+    assert_eq!(buffer, hex!("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"));
+    hash_visit::<Sha256, _>(&[1_u8;32], &[1_u8;32], &mut buffer);
+    assert_eq!(buffer, hex!("7c8975e1e60a5c8337f28edf8c33c3b180360b7279644a9bc1af3c51e6220bf5"));
+}
+
+#[cfg(feature = "blake2")]
+#[test]
+fn test_blake2b256_vectors() {
+    let mut buffer = [0_u8;32];
+    data_hash::<Blake2b256, _>("hello world".as_bytes(), &mut buffer);
+    assert_eq!(buffer, hex!("256c83b297114d201b30179f3f0ef0cace9783622da5974326b436178aeef610"));
+    hash_visit::<Blake2b256, _>(&[1_u8;32], &[1_u8;32], &mut buffer);
+    assert_eq!(buffer, hex!("c111b5c0432d505f1eeffc7bc8e38235f0ea0146d7ac506ed11bbe2e8dd844e3"));
+}
+
+#[cfg(feature = "blake3")]
+#[test]
+fn test_blake3_vectors() {
+    let mut buffer = [0_u8;32];
+    data_hash::<Blake3, _>("hello world".as_bytes(), &mut buffer);
+    assert_eq!(buffer, hex!("d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"));
+    hash_visit::<Blake3, _>(&[1_u8;32], &[1_u8;32], &mut buffer);
+    assert_eq!(buffer, hex!("29c04cc4ce7b05d941850b358b7dfd7e6285cc54e63e2e239804c1781c3d2497"));
+    let mut long = [7_u8;64];
+    <Blake3 as MerkleHasher>::hash_leaf("hello world".as_bytes(), &mut long);
+    <Blake3 as MerkleHasher>::hash_node(&[1_u8;32], &[1_u8;32], &mut long);
+    assert_eq!(long, [7_u8;64]);
+}
+
+#[test]
+fn test_rfc6962_domain_separation() {
+    let data = [1_u8;64];
+    let mut leaf = [0_u8;32];
+    let mut node = [0_u8;32];
+    data_hash::<Rfc6962<sha3::Sha3_256>, _>(&data, &mut leaf);
+    hash_visit::<Rfc6962<sha3::Sha3_256>, _>(&data[..32], &data[32..], &mut node);
+    assert_ne!(leaf, node);
+    let mut plain_leaf = [0_u8;32];
+    let mut plain_node = [0_u8;32];
+    data_hash::<sha3::Sha3_256, _>(&data, &mut plain_leaf);
+    hash_visit::<sha3::Sha3_256, _>(&data[..32], &data[32..], &mut plain_node);
+    assert_eq!(plain_leaf, plain_node); // The second preimage the RFC 6962 prefixes prevent.
+}
+
+#[test]
+fn test_payload_hash_domain_separation() {
+    let data = [1_u8;64];
+    let mut node = [0_u8;32];
+    let mut payload_leaf = [0_u8;32];
+    hash_visit::<sha3::Sha3_256, _>(&data[..32], &data[32..], &mut node);
+    assert!(payload_hash::<sha3::Sha3_256, _>(&data, &mut payload_leaf));
+    assert_ne!(payload_leaf, node);
+    let mut leaf = [0_u8;32];
+    data_hash::<sha3::Sha3_256, _>(&data, &mut leaf);
+    let mut twice = [0_u8;32];
+    data_hash::<sha3::Sha3_256, _>(&leaf, &mut twice);
+    assert_eq!(payload_leaf, twice);
+    payload_hash::<Rfc6962<sha3::Sha3_256>, _>(&data, &mut payload_leaf);
+    data_hash::<Rfc6962<sha3::Sha3_256>, _>(&data, &mut leaf);
+    assert_eq!(payload_leaf, leaf); // Already domain separated, hashed once.
+    assert!(!payload_hash::<sha3::Sha3_256, _>(&data, &mut [0_u8;28]));
+}
+
+/// Leaf inputs of the RFC 6962 test vectors, as used by the Certificate Transparency reference implementations.
+#[cfg(all(test, feature = "sha2"))]
+pub(crate) const RFC6962_TEST_LEAVES: [&[u8]; 8] = [
+    &[],
+    &[0x00],
+    &[0x10],
+    &[0x20, 0x21],
+    &[0x30, 0x31],
+    &[0x40, 0x41, 0x42, 0x43],
+    &[0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57],
+    &[0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f],
+];
+
+/// Roots of the RFC 6962 test vectors for the first 1 to 8 leaves.
+#[cfg(all(test, feature = "sha2"))]
+pub(crate) const RFC6962_TEST_ROOTS: [[u8;32]; 8] = [
+    hex!("6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"),
+    hex!("fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125"),
+    hex!("aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77"),
+    hex!("d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7"),
+    hex!("4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4"),
+    hex!("76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef"),
+    hex!("ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c"),
+    hex!("5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328"),
+];
+
+#[cfg(feature = "sha2")]
+#[test]
+fn test_rfc6962_leaf_vectors() {
+    let mut buffer = [0_u8;32];
+    data_hash::<CertificateTransparency, _>(RFC6962_TEST_LEAVES[0], &mut buffer);
+    assert_eq!(buffer, RFC6962_TEST_ROOTS[0]);
+    hash_visit::<CertificateTransparency, _>(&[], &[], &mut buffer);
+    assert_eq!(buffer, hex!("4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a")); // SHA-256(0x01)
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn rfc6962_roots_for_power_of_two_sizes() {
+    use crate::{LeMerkTree, builder::LeMerkBuilder};
+    const SIZE: usize = 32;
+    for max_depth in 0..=3 {
+        let mut tree: LeMerkTree<SIZE, CertificateTransparency> = LeMerkBuilder::<SIZE>::new()
+            .with_max_depth(max_depth)
+            .try_build::<CertificateTransparency>()
+            .expect("Unexpected build.");
+        let leaves = tree.get_leaves_indexes();
+        for (leaf_index, data) in leaves.iter().zip(RFC6962_TEST_LEAVES) {
+            let mut leaf = [0_u8; SIZE];
+            data_hash::<CertificateTransparency, _>(data, &mut leaf);
+            tree.set_and_update(*leaf_index, leaf).unwrap();
+        }
+        assert_eq!(tree.get_root_data().unwrap(), RFC6962_TEST_ROOTS[leaves.len() - 1]);
+    }
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn rfc6962_roots_for_arbitrary_sizes() {
+    use crate::{LeMerkTree, builder::LeMerkBuilder, data::OddNodePolicy};
+    const SIZE: usize = 32;
+    for leaf_count in 1..=8 {
+        let mut tree: LeMerkTree<SIZE, CertificateTransparency> = LeMerkBuilder::<SIZE>::new()
+            .with_leaf_count(leaf_count)
+            .with_odd_node_policy(OddNodePolicy::Rfc6962)
+            .try_build::<CertificateTransparency>()
+            .expect("Unexpected build.");
+        for (leaf_index, data) in tree.get_leaves_indexes().into_iter().zip(RFC6962_TEST_LEAVES) {
+            let mut leaf = [0_u8; SIZE];
+            data_hash::<CertificateTransparency, _>(data, &mut leaf);
+            tree.set_and_update(leaf_index, leaf).unwrap();
+        }
+        assert_eq!(tree.get_root_data().unwrap(), RFC6962_TEST_ROOTS[leaf_count - 1]);
+    }
+}
@@ -1,5 +1,5 @@
 use core::ops::Add;
-use alloc::vec;
+#[cfg(test)]
 use alloc::vec::Vec;
 use crate::{
     error::IndexError,
@@ -7,7 +7,29 @@ use crate::{
 };
 pub type CipherBlock = [u8;32];
 
-#[derive(Debug, PartialEq, Clone, Copy, PartialOrd)]
+/// Rule applied to a node whose pair to ancestor doesn't cover any leaf, in trees whose leaf count is not a power of two.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum OddNodePolicy {
+    /// The lone node is promoted unchanged to its ancestor.
+    #[default]
+    Promote,
+    /// The lone node is hashed with a copy of itself, Bitcoin style.
+    Duplicate,
+    /// RFC 6962 split of the leaves at the largest power of two smaller than their count.
+    /// As leaves are packed to the left of the tree, this results in the same shape as Promote.
+    Rfc6962,
+}
+
+impl OddNodePolicy {
+    /// Checks if lone nodes are promoted unchanged to their ancestor.
+    pub fn promotes(&self) -> bool {
+        matches!(self, OddNodePolicy::Promote | OddNodePolicy::Rfc6962)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
 pub struct Index(usize);
 
 impl Index {
@@ -18,41 +40,30 @@ impl Index {
         self + Index::from(1)
     }
     pub fn try_decr(self) -> Result<Index, IndexError> {
-        Ok(Index::from(self.get_index().checked_sub(1).ok_or(IndexError::IndexBadSubstraction)?) )
+        Ok(Index::from(self.get_index().checked_sub(1).ok_or(IndexError::IndexBadSubstraction(self.get_index()))?) )
     }
     pub fn try_incr(self) -> Result<Index, IndexError> {
-        Ok(Index::from(self.get_index().checked_add(1).ok_or(IndexError::IndexBadAddition)?) )
+        Ok(Index::from(self.get_index().checked_add(1).ok_or(IndexError::IndexBadAddition(self.get_index()))?) )
     }
     pub fn checked_rem(&self, value: usize) -> Result<Index, IndexError> {
-        Ok(Index::from(self.get_index().checked_rem(value).ok_or(IndexError::IndexBadRemainder)?))
+        Ok(Index::from(self.get_index().checked_rem(value).ok_or(IndexError::IndexBadRemainder(self.get_index()))?))
     }
+    /// Position in the flat hash tree, leaves first and root last: the depth k starts at 2^(max_depth+1) - 2^(k+1).
     pub fn to_flat_hash_tree_index<ST: SizedTree>(&self, tree: ST) -> Option<usize> {
-        let max_index = tree.get_max_index();
-        if self.get_index() > max_index {
-            None
-        } else {
-            let depth_offset = DepthOffset::try_from(*self).ok()?;
-            let depth = depth_offset.get_depth();
-            let offset = depth_offset.get_offset();
-            let max_depth = tree.get_max_depth();
-            if depth == max_depth {
-                Some(offset)
-            } else if depth < max_depth {
-                Some(
-                    (depth+1..=max_depth)
-                        .map(
-                            |x| {
-                                2_usize
-                                    .pow(x as u32)
-                            }
-                        )
-                        .sum::<usize>() // Sums all the element counting upto the depth (not inclusive) of the index.
-                        + offset // adds the offset to the counting.
-                    )
-            } else {
-                panic!("Unreachable.")
-            }
-        }
+        if self.get_index() > tree.get_max_index() { return None; };
+        let depth_offset = DepthOffset::try_from(*self).ok()?;
+        let flat_hash_tree_length = low_bits(tree.get_max_depth().checked_add(1)?)?;
+        let depth_start = flat_hash_tree_length.checked_sub(low_bits(depth_offset.get_depth() + 1)?)?;
+        depth_start.checked_add(depth_offset.get_offset())
+    }
+}
+
+/// 2^count - 1, i.e. the number of nodes of a perfect tree of count levels, for count up to usize::BITS.
+fn low_bits(count: usize) -> Option<usize> {
+    match count {
+        0 => Some(0),
+        count if count <= usize::BITS as usize => Some(usize::MAX >> (usize::BITS as usize - count)),
+        _ => None,
     }
 }
 
@@ -69,7 +80,8 @@ impl From<usize> for Index {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct DepthOffset(usize, usize);
 
 impl DepthOffset {
@@ -85,39 +97,14 @@ impl DepthOffset {
 }
 
 impl TryFrom<Index> for DepthOffset {
-    type Error = IndexError;    
-    /// Incrementing from i = 0, 2_usize.pow(i) - 1 is always positive until OverFlow.
-    /// With an upper bound of value + 1, the condition "2_usize.pow(i) - 1 <= value.get_index() + 1" will always conform to false in the sequence 0..9223372036854775806 with step 1.
-    /// Unless value > 9223372036854775806.
+    type Error = IndexError;
+    /// The depth k holds the indexes from 2^k - 1 to 2^(k+1) - 2, so k is the logarithm of the index + 1.
+    /// usize::MAX, whose successor doesn't fit usize, is kept as the offset 2^63 of the depth 63.
     fn try_from(value: Index) -> Result<DepthOffset, Self::Error> {
         let value = value.get_index();
-        if value < 9223372036854775806 { // The last possible level under usize precision is 63
-            let mut i: u32 = 0;
-            let mut acc: usize = 2_usize
-                .checked_pow(i+1).ok_or(IndexError::IndexBadPow)?
-                .checked_sub(1).ok_or(IndexError::IndexBadSubstraction)?;
-            while value >= acc {
-                i +=1;
-                acc = 2_usize
-                    .checked_pow(i+1).ok_or(
-                        IndexError::IndexBadPow
-                    )?
-                    .checked_sub(1).ok_or(IndexError::IndexBadSubstraction)?;
-            };
-            let prev = 2_usize
-                .checked_pow(i).ok_or(IndexError::IndexBadPow)?
-                .checked_sub(1).ok_or(IndexError::IndexBadSubstraction)?;
-            Ok(DepthOffset::from((i as usize, value-prev)))
-        } else if value == 9223372036854775807 {
-            Ok(DepthOffset::from((63, 0)))
-        } else { // For efficicency, boundary case for depth = 63 can be hardcoded. In this case, it will remain procedural with ilog(2).
-            let closest_log2 = value.checked_ilog(2).ok_or(IndexError::IndexBadilog)?;
-            let previous_layers_cardinality: usize = 2_usize
-                .checked_pow(closest_log2).ok_or(IndexError::IndexBadPow)?
-                .checked_sub(1).ok_or(IndexError::IndexBadSubstraction)?;
-            Ok(DepthOffset::from((closest_log2 as usize, value-previous_layers_cardinality)))
-        }
-
+        let depth = value.checked_add(1).map_or(usize::BITS - 1, usize::ilog2) as usize;
+        let depth_start = low_bits(depth).ok_or(IndexError::IndexBadPow(value))?;
+        Ok(DepthOffset::from((depth, value - depth_start)))
     }
 }
 
@@ -131,14 +118,11 @@ impl TryFrom<DepthOffset> for Index {
     type Error = IndexError;
     fn try_from(value: DepthOffset) -> Result<Index, Self::Error> {
         let DepthOffset(depth, offset) = value;
-        if depth > 63 || (depth == 63 && offset > 9223372036854775808) {
-            return Err(IndexError::IndexOverflow);
-        };
+        if depth >= usize::BITS as usize { return Err(IndexError::IndexOverflow { depth, offset }); };
         Ok(Index(
-            2_usize
-            .checked_pow(depth as u32).ok_or(Self::Error::IndexOverflow)?
-            .checked_sub(1).ok_or(Self::Error::IndexOverflow)?
-            .checked_add(offset).ok_or(Self::Error::IndexOverflow)?
+            low_bits(depth)
+                .and_then(|depth_start| depth_start.checked_add(offset))
+                .ok_or(IndexError::IndexOverflow { depth, offset })?
         ))
     }
 }
@@ -173,17 +157,17 @@ fn try_from_depthoffset_63_9223372036854775808() {
 
 #[test]
 fn try_from_one_more_than_max() {
-    assert_eq!(Index::try_from((63,9223372036854775808 + 1)).unwrap_err(), IndexError::IndexOverflow);
+    assert_eq!(Index::try_from((63,9223372036854775808 + 1)).unwrap_err(), IndexError::IndexOverflow { depth: 63, offset: 9223372036854775808 + 1 });
 }
 
 #[test]
 fn try_from_depthoffset_64_0_overflow() {
-    assert_eq!(Index::try_from((64,0)).unwrap_err(), IndexError::IndexOverflow);
+    assert_eq!(Index::try_from((64,0)).unwrap_err(), IndexError::IndexOverflow { depth: 64, offset: 0 });
 }
 
 #[test]
 fn try_from_depthoffset_max_overflow() {
-    assert_eq!(Index::try_from((usize::MAX,usize::MAX)).unwrap_err(), IndexError::IndexOverflow);
+    assert_eq!(Index::try_from((usize::MAX,usize::MAX)).unwrap_err(), IndexError::IndexOverflow { depth: usize::MAX, offset: usize::MAX });
 }
 
 #[test]
@@ -247,13 +231,11 @@ fn depthoffset_index_symmetry_small() {
     let initial: usize = 0;
     let n_tests = 10000;
     let _: Vec<_> = (initial..=initial+n_tests)
-        .enumerate()
-        .map( |(i,x)| {
+        .map( |x| {
             assert_eq!(
                 Index::from(x), 
                 Index::try_from(DepthOffset::try_from(Index::from(x)).unwrap()).unwrap()
             );
-            x
         })
         .collect();
 }
@@ -263,12 +245,106 @@ fn depthoffset_index_symmetry_big() {
     let initial: usize = 9223372036854775807;
     let n_tests = 10000;
     let _: Vec<_> = (initial..=initial+n_tests)
-        .enumerate()
-        .map( |(i,x)| {
+        .map( |x| {
             assert_eq!(
                 Index::from(x), 
                 Index::try_from(DepthOffset::try_from(Index::from(x)).unwrap()).unwrap()
             );
         })
         .collect();
-}
\ No newline at end of file
+}
+/// Index to DepthOffset as first implemented, looping over the powers of two, kept as the reference of the closed form.
+#[cfg(test)]
+fn reference_depth_offset(value: usize) -> DepthOffset {
+    if value < 9223372036854775806 {
+        let mut i: u32 = 0;
+        while value >= 2_usize.pow(i + 1) - 1 {
+            i += 1;
+        };
+        DepthOffset::from((i as usize, value - (2_usize.pow(i) - 1)))
+    } else if value == 9223372036854775807 {
+        DepthOffset::from((63, 0))
+    } else {
+        let closest_log2 = value.ilog2();
+        DepthOffset::from((closest_log2 as usize, value - (2_usize.pow(closest_log2) - 1)))
+    }
+}
+
+/// DepthOffset to Index as first implemented.
+#[cfg(test)]
+fn reference_index(depth: usize, offset: usize) -> Option<usize> {
+    if depth > 63 || (depth == 63 && offset > 9223372036854775808) { return None; };
+    2_usize.checked_pow(depth as u32)?.checked_sub(1)?.checked_add(offset)
+}
+
+/// Flat hash tree index as first implemented, summing the lengths of the deeper levels.
+#[cfg(test)]
+fn reference_flat_hash_tree_index(value: usize, max_depth: usize) -> Option<usize> {
+    if value > (2_usize.pow(max_depth as u32) - 1) * 2 { return None; };
+    let depth_offset = reference_depth_offset(value);
+    Some((depth_offset.get_depth() + 1..=max_depth).map(|x| 2_usize.pow(x as u32)).sum::<usize>() + depth_offset.get_offset())
+}
+
+#[cfg(test)]
+#[derive(Clone, Copy)]
+struct PerfectTree(usize);
+
+#[cfg(test)]
+impl SizedTree for PerfectTree {
+    fn get_max_index(&self) -> usize {
+        low_bits(self.0 + 1).unwrap() - 1
+    }
+    fn get_max_depth(&self) -> usize {
+        self.0
+    }
+}
+
+/// Indexes around every power of two, where the depth changes, and the top of the index space.
+#[cfg(test)]
+fn boundary_indexes() -> Vec<usize> {
+    (0..usize::BITS)
+        .flat_map(|k| {
+            let power = 1_usize << k;
+            power.saturating_sub(3)..=power.saturating_add(2)
+        })
+        .chain(usize::MAX - 1000..=usize::MAX)
+        .collect()
+}
+
+#[test]
+fn depth_offset_matches_reference() {
+    for value in (0..1 << 20).chain(boundary_indexes()) {
+        assert_eq!(DepthOffset::try_from(Index::from(value)), Ok(reference_depth_offset(value)), "index {}", value);
+    }
+}
+
+#[test]
+fn index_matches_reference() {
+    for depth in 0..=usize::BITS as usize + 1 {
+        let level_length = 1_usize.checked_shl(depth as u32).unwrap_or(0);
+        for offset in [0, 1, 2, level_length.wrapping_sub(1), level_length, level_length + 1, 1 << 63, (1 << 63) + 1, usize::MAX] {
+            assert_eq!(Index::try_from((depth, offset)).ok(), reference_index(depth, offset).map(Index::from), "depth {}, offset {}", depth, offset);
+        }
+    }
+    for value in (0..1 << 16).chain(boundary_indexes()) {
+        let depth_offset = reference_depth_offset(value);
+        assert_eq!(Index::try_from(depth_offset), Ok(Index::from(value)));
+    }
+}
+
+#[test]
+fn flat_hash_tree_index_matches_reference() {
+    for max_depth in 0..=12 {
+        let tree = PerfectTree(max_depth);
+        for value in 0..=tree.get_max_index() + 1 {
+            assert_eq!(Index::from(value).to_flat_hash_tree_index(tree), reference_flat_hash_tree_index(value, max_depth), "index {}, max depth {}", value, max_depth);
+        }
+    }
+    for max_depth in [20, 40, 62, 63] {
+        let tree = PerfectTree(max_depth);
+        for value in boundary_indexes().into_iter().filter(|value| *value <= tree.get_max_index()) {
+            assert_eq!(Index::from(value).to_flat_hash_tree_index(tree), reference_flat_hash_tree_index(value, max_depth), "index {}, max depth {}", value, max_depth);
+        }
+        assert_eq!(Index::from(0).to_flat_hash_tree_index(tree), Some(tree.get_max_index())); // The root comes last.
+    }
+}
@@ -1,85 +1,337 @@
-#[derive(Debug)]
+use core::fmt;
+use crate::format::TreeSection;
+
+/// Errors of a level or a node store, with the offending index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LeMerkLevelError {
-    Overflow,
+    /// The index is beyond the length of the level.
+    Overflow { index: usize, length: usize },
+    /// The store failed to persist the block at the index, which is only updated in memory.
+    StoreFailure { index: usize },
+    /// A lazy level needs a default block for each depth from the root, between 1 and usize::BITS of them.
+    BadDefaultCount(usize),
+}
+
+impl fmt::Display for LeMerkLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeMerkLevelError::Overflow { index, length } => write!(f, "index {} is beyond the {} blocks of the level", index, length),
+            LeMerkLevelError::StoreFailure { index } => write!(f, "the store failed to persist the block at index {}", index),
+            LeMerkLevelError::BadDefaultCount(count) => write!(f, "{} default blocks don't describe the depths of a level", count),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+impl core::error::Error for LeMerkLevelError {}
+
+/// Errors of the tree, with the offending index, depth or length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LeMerkTreeError {
-    Overflow,
-    BadDivision,
-    BadMultiplication,
-    BadAddition,
-    BadSubstraction,
-    BadRemainder,
-    BadPow,
-    Badilog,
-    IsNone,
-    OutOfBounds,
-    RuleUnmet,
+    Index(IndexError),
+    Level(LeMerkLevelError),
+    /// The index is beyond the max index of the tree.
+    Overflow { index: usize, max_index: usize },
+    /// The depth is beyond the max depth of the tree.
+    DepthOverflow { depth: usize, max_depth: usize },
+    BadDivision(usize),
+    BadMultiplication(usize),
+    BadAddition(usize),
+    BadSubstraction(usize),
+    /// 2 to the power of the depth overflows usize.
+    BadPow(usize),
+    /// A node the operation relies on doesn't exist, e.g. the pair of the root.
+    IsNone(&'static str),
+    /// The leaf offset, or the count of leaves, is beyond the leaves of the tree.
+    OutOfBounds { index: usize, length: usize },
+    /// The arguments break a rule of the operation.
+    RuleUnmet(&'static str),
+    /// The output size of the hasher differs from the block size.
+    HasherOutputSizeMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for LeMerkTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeMerkTreeError::Index(error) => write!(f, "{}", error),
+            LeMerkTreeError::Level(error) => write!(f, "{}", error),
+            LeMerkTreeError::Overflow { index, max_index } => write!(f, "index {} is beyond the max index {} of the tree", index, max_index),
+            LeMerkTreeError::DepthOverflow { depth, max_depth } => write!(f, "depth {} is beyond the max depth {} of the tree", depth, max_depth),
+            LeMerkTreeError::BadDivision(value) => write!(f, "division of {} failed", value),
+            LeMerkTreeError::BadMultiplication(value) => write!(f, "multiplication of {} overflows usize", value),
+            LeMerkTreeError::BadAddition(value) => write!(f, "addition to {} overflows usize", value),
+            LeMerkTreeError::BadSubstraction(value) => write!(f, "substraction from {} underflows usize", value),
+            LeMerkTreeError::BadPow(depth) => write!(f, "2 to the power of {} overflows usize", depth),
+            LeMerkTreeError::IsNone(node) => write!(f, "missing {}", node),
+            LeMerkTreeError::OutOfBounds { index, length } => write!(f, "{} is beyond the {} leaves of the tree", index, length),
+            LeMerkTreeError::RuleUnmet(rule) => write!(f, "rule unmet: {}", rule),
+            LeMerkTreeError::HasherOutputSizeMismatch { expected, found } => write!(f, "hasher outputs {} bytes, blocks have {}", found, expected),
+        }
+    }
 }
 
+impl core::error::Error for LeMerkTreeError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            LeMerkTreeError::Index(error) => Some(error),
+            LeMerkTreeError::Level(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Errors of the arithmetic on virtual nodes, with the offending index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VirtualNodeError {
-    Overflow,
-    BadDivision,
-    BadMultiplication,
-    BadAddition,
+    Overflow { index: usize, max_index: usize },
+    BadDivision(usize),
+    BadMultiplication(usize),
+    BadAddition(usize),
 }
 
-impl From<IndexError> for LeMerkTreeError {
-    fn from(value: IndexError) -> LeMerkTreeError {
+impl fmt::Display for VirtualNodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VirtualNodeError::Overflow { index, max_index } => write!(f, "virtual node {} is beyond the max index {} of the tree", index, max_index),
+            VirtualNodeError::BadDivision(index) => write!(f, "division of virtual node {} failed", index),
+            VirtualNodeError::BadMultiplication(index) => write!(f, "multiplication of virtual node {} overflows usize", index),
+            VirtualNodeError::BadAddition(index) => write!(f, "addition to virtual node {} overflows usize", index),
+        }
+    }
+}
+
+impl core::error::Error for VirtualNodeError {}
+
+impl From<VirtualNodeError> for LeMerkTreeError {
+    fn from(value: VirtualNodeError) -> LeMerkTreeError {
         match value {
-            IndexError::IndexOverflow => LeMerkTreeError::Overflow,
-            IndexError::IndexBadDivision => LeMerkTreeError::BadDivision,
-            IndexError::IndexBadMultiplication => LeMerkTreeError::BadMultiplication,
-            IndexError::IndexBadAddition => LeMerkTreeError::BadAddition,
-            IndexError::IndexBadSubstraction => LeMerkTreeError::BadSubstraction,
-            IndexError::IndexBadRemainder => LeMerkTreeError::BadRemainder,
-            IndexError::IndexBadPow => LeMerkTreeError::BadPow,
-            IndexError::IndexBadilog => LeMerkTreeError::Badilog,
-            _ => panic!("Unexpected error"),
+            VirtualNodeError::Overflow { index, max_index } => LeMerkTreeError::Overflow { index, max_index },
+            VirtualNodeError::BadDivision(index) => LeMerkTreeError::BadDivision(index),
+            VirtualNodeError::BadMultiplication(index) => LeMerkTreeError::BadMultiplication(index),
+            VirtualNodeError::BadAddition(index) => LeMerkTreeError::BadAddition(index),
         }
     }
 }
 
+impl From<IndexError> for LeMerkTreeError {
+    fn from(value: IndexError) -> LeMerkTreeError {
+        LeMerkTreeError::Index(value)
+    }
+}
+
 impl From<LeMerkLevelError> for LeMerkTreeError {
     fn from(value: LeMerkLevelError) -> LeMerkTreeError {
-        match value {
-            LeMerkLevelError::Overflow => LeMerkTreeError::Overflow,
-            _ => panic!("Unexpected error"),
-        }
+        LeMerkTreeError::Level(value)
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Errors of the arithmetic on indexes, with the offending index, or depth and offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IndexError {
-    IndexOverflow,
-    IndexBadDivision,
-    IndexBadMultiplication,
-    IndexBadAddition,
-    IndexBadSubstraction,
-    IndexBadRemainder,
-    IndexBadPow,
-    IndexBadilog,
+    /// The depth and offset are beyond the usize index space.
+    IndexOverflow { depth: usize, offset: usize },
+    IndexBadDivision(usize),
+    IndexBadMultiplication(usize),
+    IndexBadAddition(usize),
+    IndexBadSubstraction(usize),
+    IndexBadRemainder(usize),
+    IndexBadPow(usize),
+    IndexBadilog(usize),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::IndexOverflow { depth, offset } => write!(f, "depth {} and offset {} are beyond the usize index space", depth, offset),
+            IndexError::IndexBadDivision(index) => write!(f, "division of index {} failed", index),
+            IndexError::IndexBadMultiplication(index) => write!(f, "multiplication of index {} overflows usize", index),
+            IndexError::IndexBadAddition(index) => write!(f, "addition to index {} overflows usize", index),
+            IndexError::IndexBadSubstraction(index) => write!(f, "substraction from index {} underflows usize", index),
+            IndexError::IndexBadRemainder(index) => write!(f, "remainder of index {} failed", index),
+            IndexError::IndexBadPow(index) => write!(f, "power of 2 for index {} overflows usize", index),
+            IndexError::IndexBadilog(index) => write!(f, "logarithm of index {} failed", index),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+impl core::error::Error for IndexError {}
+
+/// Errors of the builders, with the offending depth or length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LeMerkBuilderError {
-    Overflow,
-    BadDivision,
-    BadMultiplication,
-    BadAddition,
-    BadPow,
+    Index(IndexError),
+    Level(LeMerkLevelError),
+    /// 2 to the power of the depth overflows usize.
+    BadPow(usize),
+    BadSubstraction(usize),
     LengthShouldBeGreaterThanZero,
+    /// The output size of the hasher differs from the block size.
+    HasherOutputSizeMismatch { expected: usize, found: usize },
+    /// The length of the store differs from the flat hash tree length of the max depth.
+    StoreLengthMismatch { expected: usize, found: usize },
+    /// The leaf count or odd node policy persisted by the store differs from the builder.
+    StoreTreeSectionMismatch { expected: TreeSection, found: TreeSection },
+}
+
+impl fmt::Display for LeMerkBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeMerkBuilderError::Index(error) => write!(f, "{}", error),
+            LeMerkBuilderError::Level(error) => write!(f, "{}", error),
+            LeMerkBuilderError::BadPow(depth) => write!(f, "2 to the power of depth {} overflows usize", depth),
+            LeMerkBuilderError::BadSubstraction(value) => write!(f, "substraction from {} underflows usize", value),
+            LeMerkBuilderError::LengthShouldBeGreaterThanZero => write!(f, "the tree should have at least one leaf"),
+            LeMerkBuilderError::HasherOutputSizeMismatch { expected, found } => write!(f, "hasher outputs {} bytes, blocks have {}", found, expected),
+            LeMerkBuilderError::StoreLengthMismatch { expected, found } => write!(f, "store holds {} blocks, the tree needs {}", found, expected),
+            LeMerkBuilderError::StoreTreeSectionMismatch { expected, found } => write!(
+                f,
+                "store holds a tree of {} leaves with the {:?} policy, expected {} leaves with the {:?} policy",
+                found.get_leaf_count(),
+                found.get_odd_node_policy(),
+                expected.get_leaf_count(),
+                expected.get_odd_node_policy(),
+            ),
+        }
+    }
+}
+
+impl core::error::Error for LeMerkBuilderError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            LeMerkBuilderError::Index(error) => Some(error),
+            LeMerkBuilderError::Level(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LeMerkLevelError> for LeMerkBuilderError {
+    fn from(value: LeMerkLevelError) -> LeMerkBuilderError {
+        LeMerkBuilderError::Level(value)
+    }
 }
 
 impl From<IndexError> for LeMerkBuilderError {
     fn from(value: IndexError) -> LeMerkBuilderError {
-        match value {
-            IndexError::IndexOverflow => LeMerkBuilderError::Overflow,
-            IndexError::IndexBadDivision => LeMerkBuilderError::BadDivision,
-            IndexError::IndexBadMultiplication => LeMerkBuilderError::BadMultiplication,
-            IndexError::IndexBadAddition => LeMerkBuilderError::BadAddition,
-            _ => panic!("Unexpected error"),
+        LeMerkBuilderError::Index(value)
+    }
+}
+
+/// Errors of the binary encoding, with the expected and found values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeMerkFormatError {
+    Level(LeMerkLevelError),
+    /// The size or depth doesn't fit usize, or the header.
+    Overflow(usize),
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u32),
+    BlockSizeMismatch { expected: usize, found: usize },
+    HasherMismatch { expected: u32, found: u32 },
+    /// The output size of the hasher differs from the block size.
+    HasherOutputSizeMismatch { expected: usize, found: usize },
+    /// Length in bytes of the encoding, or the file.
+    LengthMismatch { expected: usize, found: usize },
+    BadLeafCount(u64),
+    /// The depth of the header is too large for the flat hash tree length to fit usize.
+    BadMaxDepth(usize),
+    BadOddNodePolicy(u8),
+    /// The block at the flat hash tree index isn't the hash of its successors.
+    ParentHashMismatch { index: usize },
+}
+
+impl fmt::Display for LeMerkFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeMerkFormatError::Level(error) => write!(f, "{}", error),
+            LeMerkFormatError::Overflow(value) => write!(f, "{} overflows the encoding", value),
+            LeMerkFormatError::BadMagic(magic) => write!(f, "bad magic {:02x?}", magic),
+            LeMerkFormatError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            LeMerkFormatError::BlockSizeMismatch { expected, found } => write!(f, "block size is {}, expected {}", found, expected),
+            LeMerkFormatError::HasherMismatch { expected, found } => write!(f, "hasher id is {:#x}, expected {:#x}", found, expected),
+            LeMerkFormatError::HasherOutputSizeMismatch { expected, found } => write!(f, "hasher outputs {} bytes, blocks have {}", found, expected),
+            LeMerkFormatError::LengthMismatch { expected, found } => write!(f, "length is {} bytes, expected {}", found, expected),
+            LeMerkFormatError::BadLeafCount(leaf_count) => write!(f, "bad leaf count {}", leaf_count),
+            LeMerkFormatError::BadMaxDepth(max_depth) => write!(f, "bad max depth {}", max_depth),
+            LeMerkFormatError::BadOddNodePolicy(byte) => write!(f, "bad odd node policy {}", byte),
+            LeMerkFormatError::ParentHashMismatch { index } => write!(f, "block {} of the flat hash tree isn't the hash of its successors", index),
+        }
+    }
+}
+
+impl core::error::Error for LeMerkFormatError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            LeMerkFormatError::Level(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LeMerkLevelError> for LeMerkFormatError {
+    fn from(value: LeMerkLevelError) -> LeMerkFormatError {
+        LeMerkFormatError::Level(value)
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum LeMerkStoreError {
+    Io(std::io::Error),
+    Format(LeMerkFormatError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for LeMerkStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeMerkStoreError::Io(error) => write!(f, "{}", error),
+            LeMerkStoreError::Format(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::error::Error for LeMerkStoreError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            LeMerkStoreError::Io(error) => Some(error),
+            LeMerkStoreError::Format(error) => Some(error),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for LeMerkStoreError {
+    fn from(value: std::io::Error) -> LeMerkStoreError {
+        LeMerkStoreError::Io(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<LeMerkFormatError> for LeMerkStoreError {
+    fn from(value: LeMerkFormatError) -> LeMerkStoreError {
+        LeMerkStoreError::Format(value)
+    }
+}
+
+#[cfg(test)]
+use alloc::string::ToString;
+
+#[test]
+fn index_errors_convert_with_their_context() {
+    let error = IndexError::IndexBadRemainder(5);
+    assert_eq!(LeMerkTreeError::from(error), LeMerkTreeError::Index(error));
+    assert_eq!(LeMerkBuilderError::from(error), LeMerkBuilderError::Index(error));
+    let error = IndexError::IndexOverflow { depth: 64, offset: 0 };
+    assert_eq!(LeMerkBuilderError::from(error).to_string(), "depth 64 and offset 0 are beyond the usize index space");
+}
+
+#[test]
+fn errors_display_their_context() {
+    let error = LeMerkTreeError::from(LeMerkLevelError::Overflow { index: 7, length: 7 });
+    assert_eq!(error.to_string(), "index 7 is beyond the 7 blocks of the level");
+    assert!(core::error::Error::source(&error).is_some());
+    assert_eq!(LeMerkTreeError::OutOfBounds { index: 5, length: 3 }.to_string(), "5 is beyond the 3 leaves of the tree");
+    assert_eq!(LeMerkTreeError::from(VirtualNodeError::BadAddition(3)), LeMerkTreeError::BadAddition(3));
+    assert_eq!(LeMerkBuilderError::StoreLengthMismatch { expected: 7, found: 6 }.to_string(), "store holds 6 blocks, the tree needs 7");
+    assert_eq!(LeMerkFormatError::HasherMismatch { expected: 2, found: 5 }.to_string(), "hasher id is 0x5, expected 0x2");
+}
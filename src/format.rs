@@ -0,0 +1,337 @@
+use alloc::vec::Vec;
+use crate::{
+    LeMerkLevel,
+    LeMerkTree,
+    builder::depth_for_leaf_count,
+    data::{
+        Index,
+        OddNodePolicy,
+    },
+    error::LeMerkFormatError,
+    storage::FlatHashTree,
+    traits::{
+        MerkleHasher,
+        NodeStore,
+    },
+};
+
+/// Magic bytes opening every persisted LeMerk tree.
+pub const MAGIC: [u8; 4] = *b"LMRK";
+/// Version of the persisted layout, bumped on any incompatible change.
+pub const FORMAT_VERSION: u32 = 1;
+/// Length in bytes of the header, the TreeSection starts right after it.
+pub const HEADER_SIZE: usize = 32;
+/// Length in bytes of the TreeSection following the header of an encoded LeMerkTree or a store file.
+pub const TREE_SECTION_SIZE: usize = 16;
+
+/// Header of a persisted tree: magic, version, block size, max depth and hasher identifier as little endian u32,
+/// followed by reserved zero bytes up to HEADER_SIZE.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TreeHeader {
+    block_size: usize,
+    max_depth: usize,
+    hasher_id: u32,
+}
+
+impl TreeHeader {
+    pub fn new(block_size: usize, max_depth: usize, hasher_id: u32) -> Self {
+        TreeHeader {
+            block_size,
+            max_depth,
+            hasher_id,
+        }
+    }
+    /// Header of a tree of blocks of CIPHER_BLOCK_SIZE bytes hashed by H.
+    pub fn for_tree<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(max_depth: usize) -> Self {
+        TreeHeader::new(CIPHER_BLOCK_SIZE, max_depth, H::HASHER_ID)
+    }
+    pub fn get_block_size(&self) -> usize {
+        self.block_size
+    }
+    pub fn get_max_depth(&self) -> usize {
+        self.max_depth
+    }
+    pub fn get_hasher_id(&self) -> u32 {
+        self.hasher_id
+    }
+    /// Number of blocks of the flat hash tree, i.e. 2^(max_depth+1)-1.
+    pub fn get_flat_hash_tree_length(&self) -> Result<usize, LeMerkFormatError> {
+        u32::try_from(self.max_depth)
+            .ok()
+            .and_then(|max_depth| max_depth.checked_add(1))
+            .and_then(|exponent| 2_usize.checked_pow(exponent))
+            .map(|length| length - 1)
+            .ok_or(LeMerkFormatError::Overflow(self.max_depth))
+    }
+    /// Length in bytes of the header and the flat node array.
+    pub fn get_encoded_length(&self) -> Result<usize, LeMerkFormatError> {
+        self.get_flat_hash_tree_length()?
+            .checked_mul(self.block_size)
+            .and_then(|length| length.checked_add(HEADER_SIZE))
+            .ok_or(LeMerkFormatError::Overflow(self.max_depth))
+    }
+    /// Checks the header describes a tree of blocks of CIPHER_BLOCK_SIZE bytes hashed by H.
+    pub fn check<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(&self) -> Result<(), LeMerkFormatError> {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE { return Err(LeMerkFormatError::HasherOutputSizeMismatch { expected: CIPHER_BLOCK_SIZE, found: H::OUTPUT_SIZE }); };
+        if self.block_size != CIPHER_BLOCK_SIZE { return Err(LeMerkFormatError::BlockSizeMismatch { expected: CIPHER_BLOCK_SIZE, found: self.block_size }); };
+        if self.hasher_id != H::HASHER_ID { return Err(LeMerkFormatError::HasherMismatch { expected: H::HASHER_ID, found: self.hasher_id }); };
+        Ok(())
+    }
+    pub fn to_bytes(&self) -> Result<[u8; HEADER_SIZE], LeMerkFormatError> {
+        let block_size = u32::try_from(self.block_size).map_err(|_| LeMerkFormatError::Overflow(self.block_size))?;
+        let max_depth = u32::try_from(self.max_depth).map_err(|_| LeMerkFormatError::Overflow(self.max_depth))?;
+        let mut bytes = [0_u8; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes[8..12].copy_from_slice(&block_size.to_le_bytes());
+        bytes[12..16].copy_from_slice(&max_depth.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.hasher_id.to_le_bytes());
+        Ok(bytes)
+    }
+    /// Parses the header at the start of bytes, checking its magic, version and that the flat hash tree length fits usize.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LeMerkFormatError> {
+        let header = bytes.get(..HEADER_SIZE).ok_or(LeMerkFormatError::LengthMismatch { expected: HEADER_SIZE, found: bytes.len() })?;
+        let mut magic = [0_u8; 4];
+        magic.copy_from_slice(&header[0..4]);
+        if magic != MAGIC { return Err(LeMerkFormatError::BadMagic(magic)); };
+        let version = read_u32(header, 4);
+        if version != FORMAT_VERSION { return Err(LeMerkFormatError::UnsupportedVersion(version)); };
+        let max_depth = read_u32(header, 12) as usize;
+        if max_depth >= usize::BITS as usize - 1 { return Err(LeMerkFormatError::BadMaxDepth(max_depth)); };
+        Ok(
+            TreeHeader {
+                block_size: read_u32(header, 8) as usize,
+                max_depth,
+                hasher_id: read_u32(header, 16),
+            }
+        )
+    }
+}
+
+/// Section following the header of a persisted tree, holding what the flat node array doesn't tell about its shape:
+/// leaf count as little endian u64, odd node policy as u8, then reserved zero bytes up to TREE_SECTION_SIZE.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TreeSection {
+    leaf_count: usize,
+    odd_node_policy: OddNodePolicy,
+}
+
+impl TreeSection {
+    pub fn new(leaf_count: usize, odd_node_policy: OddNodePolicy) -> Self {
+        TreeSection {
+            leaf_count,
+            odd_node_policy,
+        }
+    }
+    pub fn get_leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+    pub fn get_odd_node_policy(&self) -> OddNodePolicy {
+        self.odd_node_policy
+    }
+    /// Depth of the tree holding the leaf count, checking the leaf count isn't 0.
+    pub fn get_max_depth(&self) -> Result<usize, LeMerkFormatError> {
+        if self.leaf_count == 0 { return Err(LeMerkFormatError::BadLeafCount(0)); };
+        Ok(depth_for_leaf_count(self.leaf_count))
+    }
+    pub fn to_bytes(&self) -> [u8; TREE_SECTION_SIZE] {
+        let mut bytes = [0_u8; TREE_SECTION_SIZE];
+        bytes[0..8].copy_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        bytes[8] = encode_odd_node_policy(self.odd_node_policy);
+        bytes
+    }
+    /// Parses the tree section at the start of bytes, checking its leaf count makes a tree of max_depth.
+    pub fn from_bytes(bytes: &[u8], max_depth: usize) -> Result<Self, LeMerkFormatError> {
+        let tree_section = bytes.get(..TREE_SECTION_SIZE).ok_or(LeMerkFormatError::LengthMismatch { expected: TREE_SECTION_SIZE, found: bytes.len() })?;
+        let mut leaf_count = [0_u8; 8];
+        leaf_count.copy_from_slice(&tree_section[0..8]);
+        let leaf_count = u64::from_le_bytes(leaf_count);
+        let bad_leaf_count = LeMerkFormatError::BadLeafCount(leaf_count);
+        let leaf_count = usize::try_from(leaf_count).map_err(|_| bad_leaf_count)?;
+        let odd_node_policy = decode_odd_node_policy(tree_section[8])?;
+        let tree_section = TreeSection::new(leaf_count, odd_node_policy);
+        if tree_section.get_max_depth().map_err(|_| bad_leaf_count)? != max_depth { return Err(bad_leaf_count); };
+        Ok(tree_section)
+    }
+}
+
+/// Binary encoding of a LeMerkTree: the TreeHeader, the tree section, then the flat node array from the leaves to the root.
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher, S: NodeStore<CIPHER_BLOCK_SIZE>> LeMerkTree<CIPHER_BLOCK_SIZE, H, S> {
+    pub fn encode(&self) -> Result<Vec<u8>, LeMerkFormatError> {
+        let header = TreeHeader::for_tree::<CIPHER_BLOCK_SIZE, H>(self.max_depth);
+        let mut bytes = Vec::with_capacity(header.get_encoded_length()? + TREE_SECTION_SIZE);
+        bytes.extend_from_slice(&header.to_bytes()?);
+        bytes.extend_from_slice(&TreeSection::new(self.leaf_count, self.odd_node_policy).to_bytes());
+        for index in 0..self.flat_hash_tree.len() {
+            let block = self.flat_hash_tree.get(Index::from(index))?;
+            bytes.extend_from_slice(&block);
+        }
+        Ok(bytes)
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> LeMerkTree<CIPHER_BLOCK_SIZE, H> {
+    /// Decodes a tree encoded with the same block size and hasher, into the in-memory storage.
+    /// Lengths are checked before any allocation. With verify, every node covering a leaf is rehashed from its successors
+    /// and compared to the decoded one, which costs as much as building the tree.
+    pub fn decode(bytes: &[u8], verify: bool) -> Result<Self, LeMerkFormatError> {
+        let header = TreeHeader::from_bytes(bytes)?;
+        if CIPHER_BLOCK_SIZE == 0 { return Err(LeMerkFormatError::BlockSizeMismatch { expected: CIPHER_BLOCK_SIZE, found: header.get_block_size() }); };
+        header.check::<CIPHER_BLOCK_SIZE, H>()?;
+        let encoded_length = header.get_encoded_length()?.checked_add(TREE_SECTION_SIZE).ok_or(LeMerkFormatError::Overflow(header.get_max_depth()))?;
+        if bytes.len() != encoded_length { return Err(LeMerkFormatError::LengthMismatch { expected: encoded_length, found: bytes.len() }); };
+        let max_depth = header.get_max_depth();
+        let tree_section = TreeSection::from_bytes(&bytes[HEADER_SIZE..], max_depth)?;
+        let leaf_count = tree_section.get_leaf_count();
+        let odd_node_policy = tree_section.get_odd_node_policy();
+        let data_layer_length = 2_usize.checked_pow(max_depth as u32).ok_or(LeMerkFormatError::Overflow(max_depth))?;
+        let flat_hash_tree: Vec<[u8; CIPHER_BLOCK_SIZE]> = bytes[HEADER_SIZE + TREE_SECTION_SIZE..]
+            .chunks_exact(CIPHER_BLOCK_SIZE)
+            .map(|chunk| {
+                let mut block = [0_u8; CIPHER_BLOCK_SIZE];
+                block.copy_from_slice(chunk);
+                block
+            })
+            .collect();
+        if verify {
+            verify_flat_hash_tree::<CIPHER_BLOCK_SIZE, H>(&flat_hash_tree, max_depth, leaf_count, odd_node_policy)?;
+        };
+        Ok(
+            LeMerkTree {
+                max_depth,
+                max_index: Index::from(flat_hash_tree.len() - 1),
+                flat_hash_tree: FlatHashTree::Dense(LeMerkLevel::from(flat_hash_tree)),
+                data_layer_length,
+                leaf_count,
+                odd_node_policy,
+                hasher: core::marker::PhantomData,
+            }
+        )
+    }
+}
+
+/// Rehashes the levels from the leaves, as the builder does, comparing them to the decoded nodes covering a leaf.
+fn verify_flat_hash_tree<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(flat_hash_tree: &[[u8; CIPHER_BLOCK_SIZE]], max_depth: usize, leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<(), LeMerkFormatError> {
+    let mut level = LeMerkLevel::<CIPHER_BLOCK_SIZE, H>::next_of_blocks(&flat_hash_tree[..leaf_count], odd_node_policy);
+    let mut initial_index = 1 << max_depth;
+    let mut depth = max_depth;
+    while let Some(current_level) = level {
+        depth -= 1;
+        let stored_level = &flat_hash_tree[initial_index..initial_index + current_level.0.len()];
+        if let Some(position) = stored_level.iter().zip(current_level.0.iter()).position(|(stored, computed)| stored != computed) {
+            return Err(LeMerkFormatError::ParentHashMismatch { index: initial_index + position });
+        };
+        initial_index += 1 << depth;
+        level = current_level.next_with_policy(odd_node_policy);
+    };
+    Ok(())
+}
+
+fn encode_odd_node_policy(odd_node_policy: OddNodePolicy) -> u8 {
+    match odd_node_policy {
+        OddNodePolicy::Promote => 0,
+        OddNodePolicy::Duplicate => 1,
+        OddNodePolicy::Rfc6962 => 2,
+    }
+}
+
+fn decode_odd_node_policy(byte: u8) -> Result<OddNodePolicy, LeMerkFormatError> {
+    match byte {
+        0 => Ok(OddNodePolicy::Promote),
+        1 => Ok(OddNodePolicy::Duplicate),
+        2 => Ok(OddNodePolicy::Rfc6962),
+        _ => Err(LeMerkFormatError::BadOddNodePolicy(byte)),
+    }
+}
+
+fn read_u32(header: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0_u8; 4];
+    bytes.copy_from_slice(&header[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+#[test]
+fn header_round_trip() {
+    let header = TreeHeader::for_tree::<32, sha3::Keccak256>(28);
+    let bytes = header.to_bytes().unwrap();
+    assert_eq!(TreeHeader::from_bytes(&bytes), Ok(header));
+    assert_eq!(header.get_flat_hash_tree_length(), Ok((1 << 29) - 1));
+    assert_eq!(header.check::<32, sha3::Keccak256>(), Ok(()));
+    assert_eq!(header.check::<32, sha3::Sha3_256>(), Err(LeMerkFormatError::HasherMismatch { expected: 0x02, found: 0x05 }));
+    assert_eq!(header.check::<64, sha3::Sha3_512>(), Err(LeMerkFormatError::BlockSizeMismatch { expected: 64, found: 32 }));
+    assert_eq!(header.check::<64, sha3::Keccak256>(), Err(LeMerkFormatError::HasherOutputSizeMismatch { expected: 64, found: 32 }));
+}
+
+#[test]
+fn header_rejects_foreign_bytes() {
+    let mut bytes = TreeHeader::for_tree::<32, sha3::Sha3_256>(3).to_bytes().unwrap();
+    assert_eq!(TreeHeader::from_bytes(&bytes[..HEADER_SIZE - 1]), Err(LeMerkFormatError::LengthMismatch { expected: HEADER_SIZE, found: HEADER_SIZE - 1 }));
+    bytes[4] = 2;
+    assert_eq!(TreeHeader::from_bytes(&bytes), Err(LeMerkFormatError::UnsupportedVersion(2)));
+    bytes[0] = b'X';
+    assert_eq!(TreeHeader::from_bytes(&bytes), Err(LeMerkFormatError::BadMagic(*b"XMRK")));
+    let mut bytes = TreeHeader::new(32, 3, 0x05).to_bytes().unwrap();
+    bytes[12..16].copy_from_slice(&(usize::BITS - 1).to_le_bytes());
+    assert_eq!(TreeHeader::from_bytes(&bytes), Err(LeMerkFormatError::BadMaxDepth(usize::BITS as usize - 1)));
+    bytes[12..16].copy_from_slice(&(usize::BITS - 2).to_le_bytes());
+    assert!(TreeHeader::from_bytes(&bytes).is_ok());
+    assert_eq!(TreeHeader::new(32, u32::MAX as usize, 0x05).get_flat_hash_tree_length(), Err(LeMerkFormatError::Overflow(u32::MAX as usize)));
+}
+
+#[test]
+fn encoded_tree_round_trip() {
+    use crate::builder::LeMerkBuilder;
+    const SIZE: usize = 32;
+    for (leaf_count, odd_node_policy) in [(1, OddNodePolicy::Promote), (7, OddNodePolicy::Duplicate), (11, OddNodePolicy::Rfc6962), (16, OddNodePolicy::Promote)] {
+        let leaves: Vec<[u8; SIZE]> = (0..leaf_count).map(|offset| [offset as u8 + 1; SIZE]).collect();
+        let mut tree: LeMerkTree<SIZE, sha3::Keccak256> = LeMerkBuilder::<SIZE>::new()
+            .with_leaves(leaves)
+            .with_odd_node_policy(odd_node_policy)
+            .try_build::<sha3::Keccak256>()
+            .unwrap();
+        let bytes = tree.encode().unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE + TREE_SECTION_SIZE + tree.flat_hash_tree.len() * SIZE);
+        let mut decoded_tree = LeMerkTree::<SIZE, sha3::Keccak256>::decode(&bytes, true).unwrap();
+        assert_eq!(decoded_tree, tree);
+        let leaf_index = tree.get_leaves_indexes()[0];
+        assert_eq!(decoded_tree.set_update_generate_proof(leaf_index, [0_u8; SIZE]).unwrap(), tree.set_update_generate_proof(leaf_index, [0_u8; SIZE]).unwrap());
+    }
+}
+
+#[test]
+fn decode_rejects_invalid_trees() {
+    use crate::builder::LeMerkBuilder;
+    const SIZE: usize = 32;
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new().with_leaf_count(5).try_build::<sha3::Sha3_256>().unwrap();
+    let bytes = tree.encode().unwrap();
+    assert_eq!(LeMerkTree::<SIZE, sha3::Keccak256>::decode(&bytes, false), Err(LeMerkFormatError::HasherMismatch { expected: 0x05, found: 0x02 }));
+    assert_eq!(LeMerkTree::<SIZE>::decode(&bytes[..bytes.len() - 1], false), Err(LeMerkFormatError::LengthMismatch { expected: bytes.len(), found: bytes.len() - 1 }));
+    let mut bad_leaf_count = bytes.clone();
+    bad_leaf_count[HEADER_SIZE] = 9;
+    assert_eq!(LeMerkTree::<SIZE>::decode(&bad_leaf_count, false), Err(LeMerkFormatError::BadLeafCount(9)));
+    bad_leaf_count[HEADER_SIZE] = 4; // A tree of depth 2.
+    assert_eq!(LeMerkTree::<SIZE>::decode(&bad_leaf_count, false), Err(LeMerkFormatError::BadLeafCount(4)));
+    let mut bad_policy = bytes.clone();
+    bad_policy[HEADER_SIZE + 8] = 3;
+    assert_eq!(LeMerkTree::<SIZE>::decode(&bad_policy, false), Err(LeMerkFormatError::BadOddNodePolicy(3)));
+    let mut tampered = bytes.clone();
+    tampered[HEADER_SIZE + TREE_SECTION_SIZE] ^= 1; // First byte of the first leaf.
+    assert!(LeMerkTree::<SIZE>::decode(&tampered, false).is_ok());
+    assert_eq!(LeMerkTree::<SIZE>::decode(&tampered, true), Err(LeMerkFormatError::ParentHashMismatch { index: 8 }));
+}
+
+#[test]
+fn decode_rejects_hostile_depths() {
+    use crate::builder::LeMerkBuilder;
+    const SIZE: usize = 32;
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new().with_leaf_count(5).try_build::<sha3::Sha3_256>().unwrap();
+    let bytes = tree.encode().unwrap();
+    for max_depth in [u32::MAX, 63, usize::BITS - 1] {
+        let mut hostile = bytes.clone();
+        hostile[12..16].copy_from_slice(&max_depth.to_le_bytes());
+        assert_eq!(LeMerkTree::<SIZE>::decode(&hostile, true), Err(LeMerkFormatError::BadMaxDepth(max_depth as usize)));
+    }
+    let mut hostile = bytes.clone();
+    hostile[12..16].copy_from_slice(&(usize::BITS - 2).to_le_bytes());
+    assert!(matches!(LeMerkTree::<SIZE>::decode(&hostile, true), Err(LeMerkFormatError::Overflow(_) | LeMerkFormatError::LengthMismatch { .. })));
+}
@@ -0,0 +1,234 @@
+use core::fmt;
+use core::marker::PhantomData;
+use alloc::vec::Vec;
+use crate::{
+    crypto::hash_visit,
+    data::{
+        Index,
+        DepthOffset,
+    },
+    error::LeMerkTreeError,
+    proof::{
+        MerkleProof,
+        ProofNode,
+        Side,
+    },
+    traits::MerkleHasher,
+};
+#[cfg(test)]
+use crate::{
+    LeMerkTree,
+    builder::LeMerkBuilder,
+    data::OddNodePolicy,
+};
+
+/// Append-only Merkle tree of a fixed max depth, as used by deposit contracts and note commitment trees.
+/// Leaves are appended from left to right, positions not appended yet hold the zero leaf.
+/// It only keeps the frontier, i.e. the roots of the filled left subtrees waiting for a pair, and the per-level zero hashes,
+/// so its root and proofs are the ones of a LeMerkTree of the same depth, built with the zero leaf as initial block.
+pub struct IncrementalTree<const CIPHER_BLOCK_SIZE: usize, H = sha3::Sha3_256> {
+    /// Level's length of the Merkle Tree.
+    max_depth: usize,
+    /// Number of appended leaves.
+    leaf_count: usize,
+    /// Root of the filled subtree waiting for a pair, by level from the leaves. Only levels set in the leaf count are meaningful.
+    frontier: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+    /// Root of an empty subtree, by level from the zero leaf up to the empty tree's root.
+    zero_hashes: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+    /// Root of the tree after the last append.
+    root: [u8; CIPHER_BLOCK_SIZE],
+    hasher: PhantomData<H>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> PartialEq for IncrementalTree<CIPHER_BLOCK_SIZE, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_depth == other.max_depth
+            && self.leaf_count == other.leaf_count
+            && self.frontier == other.frontier
+            && self.zero_hashes == other.zero_hashes
+            && self.root == other.root
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> fmt::Debug for IncrementalTree<CIPHER_BLOCK_SIZE, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncrementalTree")
+            .field("max_depth", &self.max_depth)
+            .field("leaf_count", &self.leaf_count)
+            .field("frontier", &self.frontier)
+            .field("zero_hashes", &self.zero_hashes)
+            .field("root", &self.root)
+            .finish()
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> Clone for IncrementalTree<CIPHER_BLOCK_SIZE, H> {
+    fn clone(&self) -> Self {
+        IncrementalTree {
+            max_depth: self.max_depth,
+            leaf_count: self.leaf_count,
+            frontier: self.frontier.clone(),
+            zero_hashes: self.zero_hashes.clone(),
+            root: self.root,
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> IncrementalTree<CIPHER_BLOCK_SIZE, H> {
+    /// Creates an empty tree, precomputing the zero hashes from the zero leaf.
+    /// It's built by LeMerkBuilder::try_build_incremental, which checks the depth and the hasher output size.
+    pub(crate) fn new(max_depth: usize, zero_leaf: [u8; CIPHER_BLOCK_SIZE]) -> Self {
+        let mut zero_hashes = Vec::with_capacity(max_depth + 1);
+        zero_hashes.push(zero_leaf);
+        for depth in 0..max_depth {
+            let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+            hash_visit::<H, _>(&zero_hashes[depth], &zero_hashes[depth], &mut output);
+            zero_hashes.push(output);
+        }
+        IncrementalTree {
+            max_depth,
+            leaf_count: 0,
+            frontier: Vec::with_capacity(max_depth),
+            root: zero_hashes[max_depth],
+            zero_hashes,
+            hasher: PhantomData,
+        }
+    }
+    pub fn get_max_depth(&self) -> usize {
+        self.max_depth
+    }
+    /// Number of appended leaves.
+    pub fn get_leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+    /// Maximum number of leaves, i.e. the data layer length of a LeMerkTree of the same depth.
+    pub fn get_capacity(&self) -> usize {
+        1 << self.max_depth
+    }
+    pub fn get_root_data(&self) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.root
+    }
+    /// Root of an empty subtree at a level counted from the leaves.
+    pub fn get_zero_hash(&self, level: usize) -> Option<[u8; CIPHER_BLOCK_SIZE]> {
+        self.zero_hashes.get(level).copied()
+    }
+    /// This method appends a leaf to the right of the last appended one.
+    /// It returns the Index of the new leaf and the root update.
+    pub fn append(&mut self, leaf: [u8; CIPHER_BLOCK_SIZE]) -> Result<(Index, [u8; CIPHER_BLOCK_SIZE]), LeMerkTreeError> {
+        let (root, proof) = self.append_generate_proof(leaf)?;
+        Ok((proof.get_index(), root))
+    }
+    /// Appends a leaf and generates its proof, compatible with the proofs of a LeMerkTree of the same depth.
+    /// The proof holds until the next append, which may replace a zero hash on its right path.
+    pub fn append_generate_proof(&mut self, leaf: [u8; CIPHER_BLOCK_SIZE]) -> Result<([u8; CIPHER_BLOCK_SIZE], MerkleProof<CIPHER_BLOCK_SIZE>), LeMerkTreeError> {
+        if self.leaf_count >= self.get_capacity() {
+            return Err(LeMerkTreeError::OutOfBounds { index: self.leaf_count, length: self.get_capacity() });
+        };
+        let offset = self.leaf_count;
+        let index = Index::try_from(DepthOffset::from((self.max_depth, offset)))?;
+        let mut siblings = Vec::with_capacity(self.max_depth);
+        let mut node = leaf;
+        let mut is_frontier_set = false;
+        for level in 0..self.max_depth {
+            let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+            if (offset >> level) & 1 == 1 {
+                // Right successor: the pair is a filled subtree of the frontier.
+                let pair = *self.frontier.get(level).ok_or(LeMerkTreeError::IsNone("frontier subtree of a right successor"))?;
+                hash_visit::<H, _>(&pair, &node, &mut output);
+                siblings.push(ProofNode::new(Side::Left, pair));
+            } else {
+                // Left successor: the pair is empty. The first one is the root of a subtree filled by this leaf.
+                if !is_frontier_set {
+                    self.set_frontier(level, node);
+                    is_frontier_set = true;
+                };
+                hash_visit::<H, _>(&node, &self.zero_hashes[level], &mut output);
+                siblings.push(ProofNode::new(Side::Right, self.zero_hashes[level]));
+            };
+            node = output;
+        }
+        self.leaf_count += 1;
+        self.root = node;
+        Ok((node, MerkleProof::new(index, self.max_depth, siblings)))
+    }
+    /// Every level below is set when a subtree is filled at this level, so the frontier grows one level at a time.
+    fn set_frontier(&mut self, level: usize, node: [u8; CIPHER_BLOCK_SIZE]) {
+        if level < self.frontier.len() {
+            self.frontier[level] = node;
+        } else {
+            self.frontier.push(node);
+        };
+    }
+}
+
+#[cfg(test)]
+fn incremental_leaf(offset: usize) -> [u8; 32] {
+    let mut leaf = [0_u8; 32];
+    crate::crypto::data_hash::<sha3::Sha3_256, _>(&offset.to_le_bytes(), &mut leaf);
+    leaf
+}
+
+#[test]
+fn empty_incremental_tree_root_is_the_zero_tree_root() {
+    const SIZE: usize = 32;
+    let builder = LeMerkBuilder::<SIZE>::new().with_max_depth(5).with_initial_block([3_u8; SIZE]);
+    let tree: IncrementalTree<SIZE> = builder.try_build_incremental::<sha3::Sha3_256>().unwrap();
+    let full_tree: LeMerkTree<SIZE> = builder.try_build::<sha3::Sha3_256>().unwrap();
+    assert_eq!(tree.get_root_data(), full_tree.get_root_data().unwrap());
+    assert_eq!(tree.get_zero_hash(5), Some(tree.get_root_data()));
+    assert_eq!(tree.get_leaf_count(), 0);
+}
+
+#[test]
+fn appends_match_full_tree_roots_and_proofs() {
+    const SIZE: usize = 32;
+    let builder = LeMerkBuilder::<SIZE>::new().with_max_depth(4);
+    let mut tree: IncrementalTree<SIZE> = builder.try_build_incremental::<sha3::Sha3_256>().unwrap();
+    let mut full_tree: LeMerkTree<SIZE> = builder.try_build::<sha3::Sha3_256>().unwrap();
+    for (offset, leaf_index) in full_tree.get_leaves_indexes().into_iter().enumerate() {
+        let leaf = incremental_leaf(offset);
+        let (root, proof) = tree.append_generate_proof(leaf).unwrap();
+        let (full_root, full_proof) = full_tree.set_update_generate_proof(leaf_index, leaf).unwrap();
+        assert_eq!(proof.get_index(), leaf_index);
+        assert_eq!(root, full_root);
+        assert_eq!(proof, full_proof);
+        assert!(proof.verify::<sha3::Sha3_256>(&root, &leaf, leaf_index, tree.get_capacity(), OddNodePolicy::default()).unwrap());
+        assert!(frontier_len_is_bounded(&tree));
+    }
+    assert_eq!(tree.get_leaf_count(), tree.get_capacity());
+    assert_eq!(tree.append([0_u8; SIZE]), Err(LeMerkTreeError::OutOfBounds { index: tree.get_capacity(), length: tree.get_capacity() }));
+}
+
+#[cfg(test)]
+fn frontier_len_is_bounded<const CIPHER_BLOCK_SIZE: usize, H>(tree: &IncrementalTree<CIPHER_BLOCK_SIZE, H>) -> bool {
+    tree.frontier.len() <= tree.max_depth
+}
+
+#[test]
+fn append_returns_leaf_index_and_root() {
+    const SIZE: usize = 32;
+    let mut tree: IncrementalTree<SIZE, sha3::Keccak256> = LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(20)
+        .try_build_incremental::<sha3::Keccak256>()
+        .unwrap();
+    let mut roots = Vec::new();
+    for offset in 0..100 {
+        let (index, root) = tree.append(incremental_leaf(offset)).unwrap();
+        assert_eq!(index, Index::try_from(DepthOffset::from((20, offset))).unwrap());
+        assert_eq!(root, tree.get_root_data());
+        assert!(!roots.contains(&root));
+        roots.push(root);
+    }
+    assert_eq!(tree.frontier.len(), 7); // 100 leaves fill subtrees up to 64 leaves.
+}
+
+#[test]
+#[should_panic(expected = "BadPow")]
+fn build_incremental_tree_beyond_index_space_should_fail() {
+    const SIZE: usize = 32;
+    let _tree: IncrementalTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(64)
+        .try_build_incremental::<sha3::Sha3_256>()
+        .unwrap();
+}
@@ -0,0 +1,119 @@
+use core::fmt;
+use alloc::vec::Vec;
+use crate::data::Index;
+#[cfg(test)]
+use alloc::vec;
+#[cfg(test)]
+use crate::{
+    crypto::hash_visit,
+    data::OddNodePolicy,
+    test_utils::distinct_leaves_tree,
+    traits::NodeStore,
+};
+
+/// An internal node whose stored block differs from the hash of its stored successors.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NodeMismatch<const CIPHER_BLOCK_SIZE: usize> {
+    index: Index,
+    /// Block recomputed from the successors, according to the odd node policy.
+    expected: [u8; CIPHER_BLOCK_SIZE],
+    stored: [u8; CIPHER_BLOCK_SIZE],
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> NodeMismatch<CIPHER_BLOCK_SIZE> {
+    pub fn new(index: Index, expected: [u8; CIPHER_BLOCK_SIZE], stored: [u8; CIPHER_BLOCK_SIZE]) -> Self {
+        NodeMismatch { index, expected, stored }
+    }
+    pub fn get_index(&self) -> Index {
+        self.index
+    }
+    pub fn get_expected(&self) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.expected
+    }
+    pub fn get_stored(&self) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.stored
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> fmt::Display for NodeMismatch<CIPHER_BLOCK_SIZE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node at index {} stores ", self.index.get_index())?;
+        self.stored.iter().try_for_each(|byte| write!(f, "{:02x}", byte))?;
+        write!(f, ", its successors hash to ")?;
+        self.expected.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> core::error::Error for NodeMismatch<CIPHER_BLOCK_SIZE> {}
+
+/// Result of checking internal nodes of a tree against their successors.
+/// Mismatches are ordered bottom-up, so the first one is the deepest inconsistent node.
+/// A corrupted node usually shows twice: against its successors, and in its ancestor.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IntegrityReport<const CIPHER_BLOCK_SIZE: usize> {
+    /// Stored root of the tree.
+    root: [u8; CIPHER_BLOCK_SIZE],
+    /// Number of internal nodes checked.
+    checked: usize,
+    mismatches: Vec<NodeMismatch<CIPHER_BLOCK_SIZE>>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> IntegrityReport<CIPHER_BLOCK_SIZE> {
+    pub fn new(root: [u8; CIPHER_BLOCK_SIZE], checked: usize, mismatches: Vec<NodeMismatch<CIPHER_BLOCK_SIZE>>) -> Self {
+        IntegrityReport { root, checked, mismatches }
+    }
+    pub fn get_root(&self) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.root
+    }
+    pub fn get_checked(&self) -> usize {
+        self.checked
+    }
+    pub fn get_mismatches(&self) -> &[NodeMismatch<CIPHER_BLOCK_SIZE>] {
+        &self.mismatches
+    }
+    pub fn get_first_mismatch(&self) -> Option<&NodeMismatch<CIPHER_BLOCK_SIZE>> {
+        self.mismatches.first()
+    }
+    /// The root if every checked node is consistent, otherwise the first mismatch.
+    pub fn get_verified_root(&self) -> Result<[u8; CIPHER_BLOCK_SIZE], NodeMismatch<CIPHER_BLOCK_SIZE>> {
+        match self.get_first_mismatch() {
+            Some(mismatch) => Err(*mismatch),
+            None => Ok(self.root),
+        }
+    }
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+#[test]
+fn corrupted_nodes_are_reported_not_panicked_on() {
+    let (mut tree, leaves) = distinct_leaves_tree(5, OddNodePolicy::Promote);
+    let leaves_indexes = tree.get_leaves_indexes();
+    let root = tree.get_root_data().unwrap();
+    let report = tree.verify_all().unwrap();
+    assert_eq!(report.get_checked(), 6);
+    assert_eq!(report.get_verified_root(), Ok(root));
+    assert!(tree.verify_path_to_root_by_index(leaves_indexes[4]).unwrap().is_consistent());
+    tree.get_flat_hash_tree_mut().put(Index::from(0), leaves[1]).unwrap(); // Raw write to the first leaf.
+    let report = tree.verify_path_to_root_by_index(leaves_indexes[0]).unwrap();
+    assert_eq!(report.get_checked(), 3);
+    let mismatch = report.get_verified_root().unwrap_err();
+    assert_eq!(mismatch.get_index(), Index::from(3));
+    assert_eq!(mismatch.get_stored(), tree.get_cipher_block_by_index(Index::from(3)).unwrap());
+    let mut expected = [0_u8; 32];
+    hash_visit::<sha3::Sha3_256, _>(&leaves[1], &leaves[1], &mut expected);
+    assert_eq!(mismatch.get_expected(), expected);
+    let message = alloc::format!("{}", mismatch);
+    assert!(message.starts_with("node at index 3 stores "));
+    let expected_hex: alloc::string::String = expected.iter().map(|byte| alloc::format!("{:02x}", byte)).collect();
+    assert!(message.ends_with(&alloc::format!(", its successors hash to {}", expected_hex)));
+    assert_eq!(tree.verify_all().unwrap().get_mismatches(), &[mismatch]);
+    assert!(tree.verify_path_to_root_by_index(leaves_indexes[2]).unwrap().is_consistent());
+    tree.recalculate(Index::from(0)).unwrap();
+    tree.get_flat_hash_tree_mut().put(Index::from(12), [0_u8; 32]).unwrap(); // Raw write to the node at depth 1, offset 0.
+    let report = tree.verify_all().unwrap();
+    let mismatched_indexes: Vec<Index> = report.get_mismatches().iter().map(NodeMismatch::get_index).collect();
+    assert_eq!(mismatched_indexes, vec![Index::from(1), Index::from(0)]);
+    assert_eq!(report.get_root(), tree.get_root_data().unwrap());
+}
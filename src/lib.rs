@@ -20,7 +20,7 @@
 
 //!    let leaf_index = leaves[0];
 //!    let (updated_root, updated_proof) = tree.set_update_generate_proof(leaf_index, different_custom_block).unwrap();
-//!    let new_root = tree.verify_proof(leaf_index, updated_proof).unwrap().unwrap();
+//!    let new_root = tree.verify_proof(&updated_proof).unwrap().unwrap();
 //!    assert_eq!(new_root, tree.get_root_data().unwrap());
 //!    assert_eq!(new_root, updated_root);
 //!    assert_ne!(new_root, original_root_data);
@@ -28,11 +28,17 @@
 //!```
 #![no_std]
 extern crate alloc;
-use sha3;
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(test)]
 use hex_literal::hex;
 use core::iter::Iterator;
+use core::marker::PhantomData;
+use core::fmt;
+#[cfg(test)]
 use alloc::vec;
 use alloc::vec::Vec;
+use alloc::collections::BTreeSet;
 /// Crypto helpers.
 pub mod crypto;
 use crypto::hash_visit;
@@ -44,24 +50,99 @@ pub use data::{
     CipherBlock,
     Index,
     DepthOffset,
+    OddNodePolicy,
 };
 pub mod error;
 use error::*;
+/// Proofs of inclusion and their stateless verification.
+pub mod proof;
+use proof::{
+    ConsistencyProof,
+    MerkleProof,
+    MultiProof,
+    ProofNode,
+    RangeProof,
+    Side,
+};
+/// Node storage layouts of the flat hash tree.
+pub mod storage;
+use storage::FlatHashTree;
+/// Append-only incremental tree.
+pub mod incremental;
+pub mod format;
+/// Integrity reports of stored trees.
+pub mod integrity;
+use integrity::{
+    IntegrityReport,
+    NodeMismatch,
+};
+#[cfg(feature = "std")]
+pub mod mmap;
+/// Sparse Merkle tree over a 256-bit key space.
+pub mod sparse;
+#[cfg(feature = "serde")]
+mod serialization;
 pub mod traits;
+#[cfg(test)]
+mod test_utils;
+#[cfg(test)]
+use test_utils::{
+    ODD_NODE_POLICIES,
+    distinct_leaves_tree,
+    distinct_leaves_trees,
+};
 use traits::SizedTree;
+pub use traits::{
+    MerkleHasher,
+    NodeStore,
+};
 
 /// Memory layout for a single layer of blocks. This is used for the expansion of the levels in the builder 
 /// and the final flatten expansion of the whole tree, in a single layer indexed by the struct implementation.
-#[derive(PartialEq, Debug, Clone)]
-pub struct LeMerkLevel<const CIPHER_BLOCK_SIZE: usize>(Vec<[u8; CIPHER_BLOCK_SIZE]>);
+/// The hasher H is the hash function used to compute the ancestors of the level.
+pub struct LeMerkLevel<const CIPHER_BLOCK_SIZE: usize, H = sha3::Sha3_256>(Vec<[u8; CIPHER_BLOCK_SIZE]>, PhantomData<H>);
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> PartialEq for LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> fmt::Debug for LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LeMerkLevel").field(&self.0).finish()
+    }
+}
 
-impl<const CIPHER_BLOCK_SIZE: usize> LeMerkLevel<CIPHER_BLOCK_SIZE> {
+impl<const CIPHER_BLOCK_SIZE: usize, H> Clone for LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    fn clone(&self) -> Self {
+        LeMerkLevel(self.0.clone(), PhantomData)
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> NodeStore<CIPHER_BLOCK_SIZE> for LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+    fn get(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError> {
+        self.get_cipher_block(index)
+    }
+    fn put(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<(), LeMerkLevelError> {
+        *self.get_cipher_block_mut_ref(index)? = block;
+        Ok(())
+    }
+    fn get_range(&self, start: usize, end: usize) -> Result<Vec<[u8; CIPHER_BLOCK_SIZE]>, LeMerkLevelError> {
+        Ok(self.0.get(start..end).ok_or(LeMerkLevelError::Overflow { index: end, length: self.0.len() })?.to_vec())
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
     pub fn get_cipher_block_mut_ref(&mut self, value: Index) -> Result<&mut [u8; CIPHER_BLOCK_SIZE], LeMerkLevelError>{
         let index_usize = value.get_index();
         if index_usize < self.0.len() {
             Ok(&mut self.0[index_usize])
         } else {
-            Err(LeMerkLevelError::Overflow)
+            Err(LeMerkLevelError::Overflow { index: index_usize, length: self.0.len() })
         }
     }
     pub fn get_cipher_block(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError>{
@@ -69,11 +150,11 @@ impl<const CIPHER_BLOCK_SIZE: usize> LeMerkLevel<CIPHER_BLOCK_SIZE> {
         if index_usize < self.0.len() {
             Ok(self.0[index_usize])
         } else {
-            Err(LeMerkLevelError::Overflow)
+            Err(LeMerkLevelError::Overflow { index: index_usize, length: self.0.len() })
         }
     }
-    pub fn from(vector: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> LeMerkLevel<CIPHER_BLOCK_SIZE> {
-        LeMerkLevel::<CIPHER_BLOCK_SIZE>(vector)
+    pub fn from(vector: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+        LeMerkLevel::<CIPHER_BLOCK_SIZE, H>(vector, PhantomData)
     }
     pub fn len(&self) -> usize {
         self.0.len()
@@ -110,8 +191,8 @@ impl<const CIPHER_BLOCK_SIZE: usize> LeMerkLevel<CIPHER_BLOCK_SIZE> {
 ///         root
 ///     );
 /// ```
-impl<const CIPHER_BLOCK_SIZE: usize> Iterator for LeMerkLevel<CIPHER_BLOCK_SIZE> {
-    type Item = LeMerkLevel<CIPHER_BLOCK_SIZE>;
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> Iterator for LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    type Item = LeMerkLevel<CIPHER_BLOCK_SIZE, H>;
     ///     
     /// ```
     /// use lemerk::LeMerkLevel;
@@ -137,19 +218,19 @@ impl<const CIPHER_BLOCK_SIZE: usize> Iterator for LeMerkLevel<CIPHER_BLOCK_SIZE>
     ///         root
     ///     );
     /// ```
-    fn next(&mut self) -> Option<LeMerkLevel<CIPHER_BLOCK_SIZE>> {
+    fn next(&mut self) -> Option<LeMerkLevel<CIPHER_BLOCK_SIZE, H>> {
         let level_length = self.len();
-        if level_length.checked_rem(2) == Some(1) {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE || level_length.checked_rem(2) == Some(1) {
             None
         } else {
-            Some(LeMerkLevel::<CIPHER_BLOCK_SIZE>::from(
+            Some(LeMerkLevel::<CIPHER_BLOCK_SIZE, H>::from(
                 (0..level_length.checked_div(2)?)
                     .map(|i| Index::from(i*2))
                     .map(|i| { 
                         let left = self.get_cipher_block(i).unwrap(); // TODO : make this unwrap infallible
                         let right = self.get_cipher_block(i.incr()).unwrap();
                         let mut output = [0_u8; CIPHER_BLOCK_SIZE];
-                        hash_visit::<sha3::Sha3_256>(&left,&right, &mut output);
+                        hash_visit::<H, _>(&left,&right, &mut output);
                         output
                     }) 
                     .collect()
@@ -165,18 +246,81 @@ impl<const CIPHER_BLOCK_SIZE: usize> Iterator for LeMerkLevel<CIPHER_BLOCK_SIZE>
     // }
 }
 
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    /// Maps the level to its next depth level closer to root, for levels of any length.
+    /// The last block of an odd length level is promoted or duplicated according to the odd node policy.
+    /// A single block level is the root, then its next level is None, as is the next level of a hasher whose output isn't a block.
+    pub fn next_with_policy(&self, policy: OddNodePolicy) -> Option<LeMerkLevel<CIPHER_BLOCK_SIZE, H>> {
+        Self::next_of_blocks(&self.0, policy)
+    }
+    /// Maps borrowed blocks to their next depth level, as next_with_policy does for a level, without copying them into one.
+    pub(crate) fn next_of_blocks(blocks: &[[u8; CIPHER_BLOCK_SIZE]], policy: OddNodePolicy) -> Option<LeMerkLevel<CIPHER_BLOCK_SIZE, H>> {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE || blocks.len() <= 1 {
+            return None;
+        };
+        Some(LeMerkLevel::<CIPHER_BLOCK_SIZE, H>::from(
+            blocks.chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => {
+                        let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+                        hash_visit::<H, _>(left, right, &mut output);
+                        output
+                    },
+                    [lone] if policy.promotes() => *lone,
+                    [lone] => {
+                        let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+                        hash_visit::<H, _>(lone, lone, &mut output);
+                        output
+                    },
+                    _ => unreachable!("chunks(2) yields one or two blocks."),
+                })
+                .collect()
+        ))
+    }
+}
+
 /// Memory layout for a LeMerk Tree.
 /// The constructor of LeMerkTree is LeMerkBuilder. 
-#[derive(PartialEq, Debug)]
-pub struct LeMerkTree<const CIPHER_BLOCK_SIZE: usize> {
+/// The hasher H the tree was built with is used for every recomputation of its nodes.
+/// The flat hash tree is held by the node store S, in memory by default.
+pub struct LeMerkTree<const CIPHER_BLOCK_SIZE: usize, H = sha3::Sha3_256, S = FlatHashTree<CIPHER_BLOCK_SIZE, H>> {
     /// Level's length of the Merkle Tree.
     max_depth: usize,
     /// Maximum possible Index
     max_index: Index,
     /// A flatten representation of the whole tree.
-    flat_hash_tree: LeMerkLevel<CIPHER_BLOCK_SIZE>,
+    flat_hash_tree: S,
     /// Length of the data layer, i.e. leaves.
-    data_layer_length: usize
+    data_layer_length: usize,
+    /// Number of leaves holding data, packed to the left of the data layer.
+    leaf_count: usize,
+    /// Rule applied to nodes whose pair to ancestor doesn't cover any leaf.
+    odd_node_policy: OddNodePolicy,
+    hasher: PhantomData<H>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H, S: PartialEq> PartialEq for LeMerkTree<CIPHER_BLOCK_SIZE, H, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_depth == other.max_depth
+            && self.max_index == other.max_index
+            && self.flat_hash_tree == other.flat_hash_tree
+            && self.data_layer_length == other.data_layer_length
+            && self.leaf_count == other.leaf_count
+            && self.odd_node_policy == other.odd_node_policy
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H, S: fmt::Debug> fmt::Debug for LeMerkTree<CIPHER_BLOCK_SIZE, H, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeMerkTree")
+            .field("max_depth", &self.max_depth)
+            .field("max_index", &self.max_index)
+            .field("flat_hash_tree", &self.flat_hash_tree)
+            .field("data_layer_length", &self.data_layer_length)
+            .field("leaf_count", &self.leaf_count)
+            .field("odd_node_policy", &self.odd_node_policy)
+            .finish()
+    }
 }
 
 /// VirtualNode is a data structure designed to be used in the context of a LeMerkTree.
@@ -202,8 +346,8 @@ impl<const CIPHER_BLOCK_SIZE: usize> VirtualNode<CIPHER_BLOCK_SIZE> {
         if self.get_index() == Index::from(0) { return Ok(None) }; // Index 0's ancestor is None.
         let index = self.index.get_index();
         let be_ancestor = index
-            .checked_sub(1).ok_or(IndexError::IndexBadSubstraction)?
-            .checked_div(2).ok_or(IndexError::IndexBadDivision)?;
+            .checked_sub(1).ok_or(IndexError::IndexBadSubstraction(index))?
+            .checked_div(2).ok_or(IndexError::IndexBadDivision(index))?;
         let ancestor: Option<Index> = if be_ancestor < index { Some(Index::from(be_ancestor)) } else { None };
         Ok(ancestor)
     }
@@ -233,24 +377,41 @@ impl<const CIPHER_BLOCK_SIZE: usize> VirtualNode<CIPHER_BLOCK_SIZE> {
     pub fn is_ancestor(&self, other: &VirtualNode<CIPHER_BLOCK_SIZE>) -> bool {
         Ok(Some(other.get_index())) == self.get_ancestor_index()
     }
+    /// Left successors have odd indexes, right successors have even indexes. The root is not a successor.
+    pub fn is_left_successor(&self) -> bool {
+        self.get_index().get_index() % 2 == 1
+    }
+    /// Side of the pair to ancestor when it's hashed with this node.
+    pub fn get_pair_side(&self) -> Side {
+        if self.is_left_successor() { Side::Right } else { Side::Left }
+    }
+}
+
+/// Hashes the data of a virtual node with the data of its pair to ancestor, in left || right order.
+fn hash_with_pair<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(virtual_node: &VirtualNode<CIPHER_BLOCK_SIZE>, node: &[u8; CIPHER_BLOCK_SIZE], pair: &[u8; CIPHER_BLOCK_SIZE], output: &mut [u8; CIPHER_BLOCK_SIZE]) {
+    if virtual_node.is_left_successor() {
+        hash_visit::<H, _>(node, pair, output);
+    } else {
+        hash_visit::<H, _>(pair, node, output);
+    }
 }
 
-impl<const CIPHER_BLOCK_SIZE: usize> LeMerkTree<CIPHER_BLOCK_SIZE> {
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher, S: NodeStore<CIPHER_BLOCK_SIZE>> LeMerkTree<CIPHER_BLOCK_SIZE, H, S> {
     pub fn get_virtual_node_by_depth_offset(&mut self, value: DepthOffset) -> Result<VirtualNode<CIPHER_BLOCK_SIZE>, LeMerkTreeError> {
         let index = Index::try_from(value)?;
         self.get_virtual_node_by_index(index)
     }
     pub fn get_virtual_node_by_index(&self, index: Index) -> Result<VirtualNode<CIPHER_BLOCK_SIZE>, LeMerkTreeError> {
-        if index > self.max_index { return Err(LeMerkTreeError::Overflow); };
-        let flat_tree_index = index.to_flat_hash_tree_index(self).ok_or(IndexError::IndexOverflow)?;
+        if index > self.max_index { return Err(LeMerkTreeError::Overflow { index: index.get_index(), max_index: self.max_index.get_index() }); };
+        let flat_tree_index = index.to_flat_hash_tree_index(self).ok_or(LeMerkTreeError::Overflow { index: index.get_index(), max_index: self.max_index.get_index() })?;
         let depth_offset = DepthOffset::try_from(index)?;
         let ancestor = if index.get_index() == 0 {
                 None 
             } else {
                 Some(Index::from(
                     index.get_index()
-                        .checked_sub(1).ok_or(IndexError::IndexBadSubstraction)?
-                        .checked_div(2).ok_or(LeMerkTreeError::BadDivision)?
+                        .checked_sub(1).ok_or(IndexError::IndexBadSubstraction(index.get_index()))?
+                        .checked_div(2).ok_or(LeMerkTreeError::BadDivision(index.get_index()))?
                     )
                 )
             };
@@ -258,21 +419,21 @@ impl<const CIPHER_BLOCK_SIZE: usize> LeMerkTree<CIPHER_BLOCK_SIZE> {
                 (None, None)
             } else {
                 let double_index = index.get_index()
-                    .checked_mul(2).ok_or(LeMerkTreeError::BadMultiplication)?;
+                    .checked_mul(2).ok_or(LeMerkTreeError::BadMultiplication(index.get_index()))?;
                 (
                     Some(Index::from(
                         double_index
-                            .checked_add(1).ok_or(LeMerkTreeError::BadAddition)?
+                            .checked_add(1).ok_or(LeMerkTreeError::BadAddition(double_index))?
                     )),
                     Some(Index::from(
                         double_index
-                            .checked_add(2).ok_or(LeMerkTreeError::BadAddition)?
+                            .checked_add(2).ok_or(LeMerkTreeError::BadAddition(double_index))?
                     )),
                 )
             };
         Ok(
             VirtualNode {
-                // data_hash: self.flat_hash_tree.get_cipher_block(index)?,
+                // data_hash: self.flat_hash_tree.get(index)?,
                 index,
                 flat_tree_index,
                 ancestor,
@@ -284,23 +445,31 @@ impl<const CIPHER_BLOCK_SIZE: usize> LeMerkTree<CIPHER_BLOCK_SIZE> {
     pub fn get_data_layer_length(&self) -> usize {
         self.data_layer_length
     }
-    pub fn get_level_by_depth_index(&self, depth: usize) -> Result<LeMerkLevel<CIPHER_BLOCK_SIZE>, LeMerkTreeError> {
+    /// Number of leaves holding data, lower or equal than the data layer length.
+    pub fn get_leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+    pub fn get_odd_node_policy(&self) -> OddNodePolicy {
+        self.odd_node_policy
+    }
+    pub fn get_level_by_depth_index(&self, depth: usize) -> Result<LeMerkLevel<CIPHER_BLOCK_SIZE, H>, LeMerkTreeError> {
         let max_index = self.max_index.get_index();
         if depth > self.max_depth {
-            Err(LeMerkTreeError::Overflow)
+            Err(LeMerkTreeError::DepthOverflow { depth, max_depth: self.max_depth })
         } else {
-            let initial_index = max_index + 1 - 2_usize.checked_pow(depth as u32 + 1).ok_or(LeMerkTreeError::BadPow)?.checked_sub(1).ok_or(LeMerkTreeError::BadSubstraction)?;
-            let level_size = 2_usize.checked_pow(depth as u32).ok_or(LeMerkTreeError::BadPow)?;
+            let initial_index = max_index + 1 - 2_usize.checked_pow(depth as u32 + 1).ok_or(LeMerkTreeError::BadPow(depth + 1))?.checked_sub(1).ok_or(LeMerkTreeError::BadSubstraction(depth))?;
+            let subtree_leaves = 2_usize.checked_pow((self.max_depth - depth) as u32).ok_or(LeMerkTreeError::BadPow(self.max_depth - depth))?;
+            let level_size = self.leaf_count.div_ceil(subtree_leaves); // Only the nodes covering leaves.
             let ending_index = initial_index + level_size;
             Ok(LeMerkLevel::from(
-                self.flat_hash_tree.0[initial_index..ending_index].to_vec()
+                self.flat_hash_tree.get_range(initial_index, ending_index)?
             ))
         }
     }
     pub fn get_leaves_indexes(&self) -> Vec<Index> {
         let max_depth = self.get_max_depth();
         let cardinality = 2_usize.pow(max_depth as u32)-1;
-        (0..=cardinality)
+        (0..self.leaf_count)
             .map(
                 |offset| {
                     cardinality + offset
@@ -331,144 +500,374 @@ impl<const CIPHER_BLOCK_SIZE: usize> LeMerkTree<CIPHER_BLOCK_SIZE> {
         };
         Ok(result)
     }
-    pub fn verify_path_to_root_by_index(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
-        let mut result = self.get_cipher_block_by_index(index)?;
+    /// Checks every ancestor of a node, from the node to the root, against the hash of its stored successors.
+    /// Empty ancestors aren't checked. Inconsistent nodes are reported, see IntegrityReport::get_verified_root.
+    pub fn verify_path_to_root_by_index(&self, index: Index) -> Result<IntegrityReport<CIPHER_BLOCK_SIZE>, LeMerkTreeError> {
         let mut virtual_node = self.get_virtual_node_by_index(index)?;
+        let mut checked = 0;
+        let mut mismatches = Vec::new();
         while let Some(ancestor_index) = virtual_node.get_ancestor_index()? {
-            let virtual_node_flat_tree_index = virtual_node.get_flat_tree_index();
-            let pair_to_ancestor_flat_tree_index = self.get_virtual_node_by_index(
-                    virtual_node.get_pair_index_to_ancestor()?.ok_or(LeMerkTreeError::IsNone)?
-                )?.get_flat_tree_index();
-            let ancestor_virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
-            let ancestor_flat_tree_index = ancestor_virtual_node.get_flat_tree_index();
-            
-            hash_visit::<sha3::Sha3_256>(
-                &self.flat_hash_tree.get_cipher_block(virtual_node_flat_tree_index.into())?,
-                &self.flat_hash_tree.get_cipher_block(pair_to_ancestor_flat_tree_index.into())?,
-                &mut result
-            );
-            assert_eq!(self.flat_hash_tree.get_cipher_block(ancestor_flat_tree_index.into())?, result);
             virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
+            if self.is_empty_node(ancestor_index)? { continue; };
+            checked += 1;
+            if let Some(mismatch) = self.check_node(&virtual_node)? {
+                mismatches.push(mismatch);
+            };
         };
-        self.get_root_data()
+        Ok(IntegrityReport::new(self.get_root_data()?, checked, mismatches))
+    }
+    /// Checks every internal node covering a leaf against the hash of its stored successors, level by level from the leaves.
+    /// It reports all the mismatches, and costs as much as building the tree.
+    pub fn verify_all(&self) -> Result<IntegrityReport<CIPHER_BLOCK_SIZE>, LeMerkTreeError> {
+        let mut checked = 0;
+        let mut mismatches = Vec::new();
+        for depth in (0..self.max_depth).rev() {
+            let subtree_leaves = 1_usize.checked_shl((self.max_depth - depth) as u32).ok_or(LeMerkTreeError::BadPow(self.max_depth - depth))?;
+            for offset in 0..self.leaf_count.div_ceil(subtree_leaves) { // Only the nodes covering leaves.
+                let virtual_node = self.get_virtual_node_by_index(Index::try_from(DepthOffset::from((depth, offset)))?)?;
+                checked += 1;
+                if let Some(mismatch) = self.check_node(&virtual_node)? {
+                    mismatches.push(mismatch);
+                };
+            }
+        };
+        Ok(IntegrityReport::new(self.get_root_data()?, checked, mismatches))
     }
     /// This method sets a leaf by its index with the block data provided.
     /// It returns the root update.
     pub fn set_and_update(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
-        let mut virtual_node = self.get_virtual_node_by_index(index)?;
-        let flat_tree_index_to_update = virtual_node.get_flat_tree_index();
-        if flat_tree_index_to_update >= self.get_data_layer_length() {
-            Err(LeMerkTreeError::OutOfBounds)
-        } else {
-            *self.flat_hash_tree.get_cipher_block_mut_ref(flat_tree_index_to_update.into())? = block;
-            let mut result = self.get_cipher_block_by_index(index)?;
-            while let Some(ancestor_index) = virtual_node.get_ancestor_index()? {
-                let virtual_node_flat_tree_index = virtual_node.get_flat_tree_index();
-                let pair_to_ancestor_flat_tree_index = self.get_virtual_node_by_index(
-                        virtual_node.get_pair_index_to_ancestor()?.ok_or(LeMerkTreeError::IsNone)?
-                    )?.get_flat_tree_index();
-                let ancestor_virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
-                let ancestor_flat_tree_index = ancestor_virtual_node.get_flat_tree_index();
-                
-                hash_visit::<sha3::Sha3_256>(
-                    &self.flat_hash_tree.get_cipher_block(virtual_node_flat_tree_index.into())?,
-                    &self.flat_hash_tree.get_cipher_block(pair_to_ancestor_flat_tree_index.into())?,
-                    &mut result
-                );
-                *self.flat_hash_tree.get_cipher_block_mut_ref(ancestor_flat_tree_index.into())? = result;
-                virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
-            };
-            Ok(self.flat_hash_tree.get_cipher_block(virtual_node.get_flat_tree_index().into())?)
-        }
+        let (root, _) = self.set_update_generate_proof(index, block)?;
+        Ok(root)
+    }
+    /// This method sets a leaf by its index with the hash of an arbitrary length payload, as LeMerkBuilder::with_leaf_data.
+    /// It returns the root update.
+    pub fn set_leaf_data(&mut self, index: Index, data: &[u8]) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
+        let mut block = [0_u8; CIPHER_BLOCK_SIZE];
+        crypto::payload_hash::<H, _>(data, &mut block);
+        self.set_and_update(index, block)
     }
-    pub fn set_update_generate_proof(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<([u8; CIPHER_BLOCK_SIZE], Vec<[u8; CIPHER_BLOCK_SIZE]>), LeMerkTreeError> {
+    /// This method sets a batch of leaves by their index with the block data provided, the last update of a leaf wins.
+    /// Every ancestor of the updated leaves is recomputed once, level by level. It returns the root update.
+    /// No leaf is written if any index isn't a leaf of the tree.
+    pub fn set_many(&mut self, updates: &[(Index, [u8; CIPHER_BLOCK_SIZE])]) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
+        let flat_tree_indexes = updates
+            .iter()
+            .map(|(index, _)| {
+                let flat_tree_index = self.get_virtual_node_by_index(*index)?.get_flat_tree_index();
+                if flat_tree_index >= self.get_leaf_count() { Err(LeMerkTreeError::OutOfBounds { index: flat_tree_index, length: self.get_leaf_count() }) } else { Ok(flat_tree_index) }
+            })
+            .collect::<Result<Vec<usize>, LeMerkTreeError>>()?;
+        let blocks: Vec<(Index, [u8; CIPHER_BLOCK_SIZE])> = flat_tree_indexes
+            .into_iter()
+            .zip(updates.iter())
+            .map(|(flat_tree_index, (_, block))| (Index::from(flat_tree_index), *block))
+            .collect();
+        self.flat_hash_tree.put_batch(&blocks)?;
+        self.recalculate_ancestors(updates.iter().map(|(index, _)| *index))
+    }
+    pub fn set_update_generate_proof(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<([u8; CIPHER_BLOCK_SIZE], MerkleProof<CIPHER_BLOCK_SIZE>), LeMerkTreeError> {
         let mut virtual_node = self.get_virtual_node_by_index(index)?;
         let flat_tree_index_to_update = virtual_node.get_flat_tree_index();
-        let mut proof = Vec::new();
-        if flat_tree_index_to_update >= self.get_data_layer_length() {
-            Err(LeMerkTreeError::OutOfBounds)
+        let mut siblings = Vec::new();
+        if flat_tree_index_to_update >= self.get_leaf_count() {
+            Err(LeMerkTreeError::OutOfBounds { index: flat_tree_index_to_update, length: self.get_leaf_count() })
         } else {
-            *self.flat_hash_tree.get_cipher_block_mut_ref(flat_tree_index_to_update.into())? = block;
-            let mut result = self.get_cipher_block_by_index(index)?;
+            let mut blocks = Vec::with_capacity(self.max_depth + 1);
+            blocks.push((Index::from(flat_tree_index_to_update), block));
+            let mut result = block;
             while let Some(ancestor_index) = virtual_node.get_ancestor_index()? {
-                let virtual_node_flat_tree_index = virtual_node.get_flat_tree_index();
-                let pair_to_ancestor_flat_tree_index = self.get_virtual_node_by_index(
-                        virtual_node.get_pair_index_to_ancestor()?.ok_or(LeMerkTreeError::IsNone)?
-                    )?.get_flat_tree_index();
                 let ancestor_virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
-                let ancestor_flat_tree_index = ancestor_virtual_node.get_flat_tree_index();
-                let node_a = self.flat_hash_tree.get_cipher_block(virtual_node_flat_tree_index.into())?;
-                let node_b = self.flat_hash_tree.get_cipher_block(pair_to_ancestor_flat_tree_index.into())?;
-                hash_visit::<sha3::Sha3_256>(
-                    &node_a,
-                    &node_b,
-                    &mut result
-                );
-                proof.push(node_b);
-                *self.flat_hash_tree.get_cipher_block_mut_ref(ancestor_flat_tree_index.into())? = result;
-                virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
+                let (ancestor_data, proof_node) = self.hash_to_ancestor(&virtual_node, &result)?;
+                if let Some(proof_node) = proof_node {
+                    siblings.push(ProofNode::new(virtual_node.get_pair_side(), proof_node));
+                };
+                result = ancestor_data;
+                blocks.push((Index::from(ancestor_virtual_node.get_flat_tree_index()), result));
+                virtual_node = ancestor_virtual_node;
             };
-            Ok((self.flat_hash_tree.get_cipher_block(virtual_node.get_flat_tree_index().into())?, proof))
+            self.flat_hash_tree.put_batch(&blocks)?;
+            Ok((result, MerkleProof::new(index, self.max_depth, siblings)))
         }
     }
     /// Generates a proof from a node index corresponding to a leaf in a LeMerkTree.
-    /// A proof is defined a a tuple of a root and a collection of data blocks.
+    /// A proof is defined a a tuple of a root and a MerkleProof, the collection of sibling blocks with their side.
     /// For every given state of a LeMerkTree, there's a unique proof for every leaf in the tree.
-    pub fn generate_proof(&mut self, index: Index) -> Result<([u8; CIPHER_BLOCK_SIZE], Vec<[u8; CIPHER_BLOCK_SIZE]>), LeMerkTreeError> {
+    /// Levels where the leaf's path is promoted by the odd node policy don't contribute a block to the proof.
+    pub fn generate_proof(&mut self, index: Index) -> Result<([u8; CIPHER_BLOCK_SIZE], MerkleProof<CIPHER_BLOCK_SIZE>), LeMerkTreeError> {
         let mut virtual_node = self.get_virtual_node_by_index(index)?;
         let flat_tree_index_to_update = virtual_node.get_flat_tree_index();
-        if flat_tree_index_to_update >= self.get_data_layer_length() {
-            Err(LeMerkTreeError::OutOfBounds)
+        if flat_tree_index_to_update >= self.get_leaf_count() {
+            Err(LeMerkTreeError::OutOfBounds { index: flat_tree_index_to_update, length: self.get_leaf_count() })
         } else {
-            let mut proof = Vec::new();
+            let mut siblings = Vec::new();
             while let Some(ancestor_index) = virtual_node.get_ancestor_index()? {
-                let pair_to_ancestor_flat_tree_index = self.get_virtual_node_by_index(
-                        virtual_node.get_pair_index_to_ancestor()?.ok_or(LeMerkTreeError::IsNone)?
-                    )?.get_flat_tree_index();
-                let pair_node_to_ancestor_data = self.flat_hash_tree.get_cipher_block(pair_to_ancestor_flat_tree_index.into())?;
-                proof.push(pair_node_to_ancestor_data);
+                let node = self.flat_hash_tree.get(virtual_node.get_flat_tree_index().into())?;
+                if let Some(proof_node) = self.get_proof_node(&virtual_node, &node)? {
+                    siblings.push(ProofNode::new(virtual_node.get_pair_side(), proof_node));
+                };
                 virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
             }
-            Ok((self.flat_hash_tree.get_cipher_block(virtual_node.get_flat_tree_index().into())?, proof))
+            Ok((self.flat_hash_tree.get(virtual_node.get_flat_tree_index().into())?, MerkleProof::new(index, self.max_depth, siblings)))
         }
     }
-    pub fn verify_proof(&self, index: Index, proof: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError> {
-        if proof.len() <= self.get_max_depth() {
-            let cipher_block = self.get_cipher_block_by_index(index)?;
-            let mut virtual_node = self.get_virtual_node_by_index(index)?;
-            let hash_set = proof;
-            let mut visited = self.get_cipher_block_by_index(index)?;
-            for hash in hash_set {
-                if let Some(ancestor_index) = virtual_node.get_ancestor_index()? {
-                    let ancestor_data = self.get_cipher_block_by_index(ancestor_index)?;
-                    let mut output = [0_u8; CIPHER_BLOCK_SIZE];
-                    hash_visit::<sha3::Sha3_256>(&visited, &hash, &mut output);
-                    if ancestor_data != output {
+    /// Generates a multiproof for a set of leaves of a LeMerkTree, given by their node index in any order.
+    /// A multiproof is defined as a tuple of a root and a MultiProof, holding the minimal set of sibling blocks for the leaves.
+    pub fn generate_multiproof(&self, indexes: &[Index]) -> Result<([u8; CIPHER_BLOCK_SIZE], MultiProof<CIPHER_BLOCK_SIZE>), LeMerkTreeError> {
+        let mut indexes = indexes.to_vec();
+        indexes.sort();
+        indexes.dedup();
+        let leaves = indexes
+            .iter()
+            .map(|index| {
+                let flat_tree_index = self.get_virtual_node_by_index(*index)?.get_flat_tree_index();
+                if flat_tree_index >= self.get_leaf_count() { return Err(LeMerkTreeError::OutOfBounds { index: flat_tree_index, length: self.get_leaf_count() }); };
+                Ok(self.flat_hash_tree.get(flat_tree_index.into())?)
+            })
+            .collect::<Result<Vec<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError>>()?;
+        let mut siblings = Vec::new();
+        let root = proof::fold_multiproof::<CIPHER_BLOCK_SIZE, H, _>(
+            &indexes,
+            &leaves,
+            self.max_depth,
+            self.leaf_count,
+            self.odd_node_policy,
+            |depth_offset| {
+                let sibling = self.get_cipher_block_by_index(Index::try_from(depth_offset)?)?;
+                siblings.push(sibling);
+                Ok(Some(sibling))
+            },
+        )?.ok_or(LeMerkTreeError::IsNone("root of the proven leaves"))?;
+        Ok((root, MultiProof::new(indexes, self.max_depth, siblings)))
+    }
+    /// Generates a range proof for the contiguous leaves from the start to the end leaf, both included.
+    /// A range proof is defined as a tuple of a root and a RangeProof, holding the boundary paths of the range.
+    pub fn generate_range_proof(&self, start: DepthOffset, end: DepthOffset) -> Result<([u8; CIPHER_BLOCK_SIZE], RangeProof<CIPHER_BLOCK_SIZE>), LeMerkTreeError> {
+        let indexes = proof::range_indexes(start, end)?;
+        if start.get_depth() != self.max_depth { return Err(LeMerkTreeError::RuleUnmet("a range proof spans leaves")); };
+        if end.get_offset() >= self.leaf_count { return Err(LeMerkTreeError::OutOfBounds { index: end.get_offset(), length: self.leaf_count }); };
+        let leaves = self.flat_hash_tree.get_range(start.get_offset(), end.get_offset() + 1)?;
+        let mut left_path = Vec::new();
+        let mut right_path = Vec::new();
+        let root = proof::fold_multiproof::<CIPHER_BLOCK_SIZE, H, _>(
+            &indexes,
+            &leaves,
+            self.max_depth,
+            self.leaf_count,
+            self.odd_node_policy,
+            |depth_offset| {
+                let sibling = self.get_cipher_block_by_index(Index::try_from(depth_offset)?)?;
+                if proof::is_left_of_range(start, depth_offset) {
+                    left_path.push(sibling);
+                } else {
+                    right_path.push(sibling);
+                };
+                Ok(Some(sibling))
+            },
+        )?.ok_or(LeMerkTreeError::IsNone("root of the proven leaves"))?;
+        Ok((root, RangeProof::new(start, end, left_path, right_path)))
+    }
+    /// Generates an RFC 6962 consistency proof between the prefixes of the tree holding old size and new size leaves.
+    /// Prefix roots are the RFC 6962 roots only with the promote shape, so the Duplicate odd node policy is rejected.
+    pub fn generate_consistency_proof(&self, old_size: usize, new_size: usize) -> Result<ConsistencyProof<CIPHER_BLOCK_SIZE>, LeMerkTreeError> {
+        if !self.odd_node_policy.promotes() { return Err(LeMerkTreeError::RuleUnmet("consistency proofs need a promoting odd node policy")); };
+        if old_size == 0 || old_size > new_size { return Err(LeMerkTreeError::RuleUnmet("consistency proofs need 0 < old size <= new size")); };
+        if new_size > self.leaf_count { return Err(LeMerkTreeError::OutOfBounds { index: new_size, length: self.leaf_count }); };
+        let mut nodes = Vec::new();
+        self.consistency_subproof(old_size, 0, new_size, true, &mut nodes)?;
+        Ok(ConsistencyProof::new(old_size, new_size, nodes))
+    }
+    /// Root of the prefix of the tree holding the first leaf count leaves, as RFC 6962 computes it.
+    pub fn get_root_data_by_leaf_count(&self, leaf_count: usize) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
+        if !self.odd_node_policy.promotes() { return Err(LeMerkTreeError::RuleUnmet("prefix roots need a promoting odd node policy")); };
+        if leaf_count == 0 { return Err(LeMerkTreeError::RuleUnmet("prefix roots need at least one leaf")); };
+        if leaf_count > self.leaf_count { return Err(LeMerkTreeError::OutOfBounds { index: leaf_count, length: self.leaf_count }); };
+        self.get_range_hash(0, leaf_count)
+    }
+    /// RFC 6962 SUBPROOF of the old size leaves within the leaves from start to end, excluded.
+    fn consistency_subproof(&self, old_size: usize, start: usize, end: usize, is_complete_subtree: bool, nodes: &mut Vec<[u8; CIPHER_BLOCK_SIZE]>) -> Result<(), LeMerkTreeError> {
+        let size = end - start;
+        if old_size == size {
+            if !is_complete_subtree {
+                nodes.push(self.get_range_hash(start, end)?);
+            };
+            return Ok(());
+        };
+        let split = largest_power_of_two_below(size);
+        if old_size <= split {
+            self.consistency_subproof(old_size, start, start + split, is_complete_subtree, nodes)?;
+            nodes.push(self.get_range_hash(start + split, end)?);
+        } else {
+            self.consistency_subproof(old_size - split, start + split, end, false, nodes)?;
+            nodes.push(self.get_range_hash(start, start + split)?);
+        };
+        Ok(())
+    }
+    /// RFC 6962 hash of the leaves from start to end, excluded. Start is aligned to the smallest power of two holding the range.
+    /// The range is a stored node when it ends at its subtree's boundary or at the last leaf, otherwise it's split as RFC 6962 does.
+    fn get_range_hash(&self, start: usize, end: usize) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
+        let size = end - start;
+        let height = size.next_power_of_two().trailing_zeros() as usize;
+        let subtree_end = start.checked_add(1 << height).ok_or(LeMerkTreeError::BadAddition(start))?.min(self.leaf_count);
+        if end == subtree_end {
+            let depth = self.max_depth.checked_sub(height).ok_or(LeMerkTreeError::BadSubstraction(self.max_depth))?;
+            self.get_cipher_block_by_index(Index::try_from(DepthOffset::from((depth, start >> height)))?)
+        } else {
+            let split = largest_power_of_two_below(size);
+            let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+            hash_visit::<H, _>(&self.get_range_hash(start, start + split)?, &self.get_range_hash(start + split, end)?, &mut output);
+            Ok(output)
+        }
+    }
+    /// Verifies a proof against the tree, checking every ancestor it computes against the stored one.
+    /// Returns the root computed by the proof, or None if the proof doesn't match the tree.
+    pub fn verify_proof(&self, proof: &MerkleProof<CIPHER_BLOCK_SIZE>) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError> {
+        if proof.len() <= self.get_max_depth() && proof.get_depth() == self.get_max_depth() {
+            let mut virtual_node = self.get_virtual_node_by_index(proof.get_index())?;
+            let mut hash_set = proof.get_siblings().iter();
+            let mut visited = self.get_cipher_block_by_index(proof.get_index())?;
+            while let Some(ancestor_index) = virtual_node.get_ancestor_index()? {
+                let ancestor_data = self.get_cipher_block_by_index(ancestor_index)?;
+                let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+                if self.is_promoted(&virtual_node)? {
+                    output = visited;
+                } else if let Some(sibling) = hash_set.next() {
+                    if sibling.get_side() != virtual_node.get_pair_side() {
                         return Ok(None);
                     };
-                    visited = output;
-                    virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
-                }
+                    hash_with_pair::<CIPHER_BLOCK_SIZE, H>(&virtual_node, &visited, &sibling.get_block(), &mut output);
+                } else {
+                    return Ok(None); // The proof is shorter than the path to root.
+                };
+                if ancestor_data != output {
+                    return Ok(None);
+                };
+                visited = output;
+                virtual_node = self.get_virtual_node_by_index(ancestor_index)?;
             }
+            if hash_set.next().is_some() {
+                return Ok(None); // The proof is longer than the path to root.
+            };
             Ok(Some(visited))
         } else {
             Ok(None)
         }
     }
+    /// Checks if a node doesn't cover any leaf of the tree, i.e. its leftmost leaf is beyond the leaf count.
+    pub fn is_empty_node(&self, index: Index) -> Result<bool, LeMerkTreeError> {
+        if index > self.max_index { return Err(LeMerkTreeError::Overflow { index: index.get_index(), max_index: self.max_index.get_index() }); };
+        let depth_offset = DepthOffset::try_from(index)?;
+        let leftmost_leaf_offset = depth_offset.get_offset()
+            .checked_shl((self.max_depth - depth_offset.get_depth()) as u32).ok_or(LeMerkTreeError::BadPow(self.max_depth - depth_offset.get_depth()))?;
+        Ok(leftmost_leaf_offset >= self.leaf_count)
+    }
+    /// Checks if a node is promoted to its ancestor, i.e. its pair to ancestor is empty and the odd node policy promotes lone nodes.
+    fn is_promoted(&self, virtual_node: &VirtualNode<CIPHER_BLOCK_SIZE>) -> Result<bool, LeMerkTreeError> {
+        let pair_index = virtual_node.get_pair_index_to_ancestor()?.ok_or(LeMerkTreeError::IsNone("pair to ancestor of the root"))?;
+        Ok(self.odd_node_policy.promotes() && self.is_empty_node(pair_index)?)
+    }
+    /// Gets the block a proof carries for the level of a virtual node holding the node data: the data of its pair to ancestor,
+    /// the node data itself if the pair is empty and duplicated, or None if the node is promoted.
+    fn get_proof_node(&self, virtual_node: &VirtualNode<CIPHER_BLOCK_SIZE>, node: &[u8; CIPHER_BLOCK_SIZE]) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError> {
+        let pair_index = virtual_node.get_pair_index_to_ancestor()?.ok_or(LeMerkTreeError::IsNone("pair to ancestor of the root"))?;
+        if !self.is_empty_node(pair_index)? {
+            Ok(Some(self.get_cipher_block_by_index(pair_index)?))
+        } else if self.odd_node_policy.promotes() {
+            Ok(None)
+        } else {
+            Ok(Some(*node))
+        }
+    }
+    /// Computes the ancestor data of a virtual node holding the node data, according to the odd node policy.
+    /// It also returns the block a proof carries for this level, if any.
+    fn hash_to_ancestor(&self, virtual_node: &VirtualNode<CIPHER_BLOCK_SIZE>, node: &[u8; CIPHER_BLOCK_SIZE]) -> Result<([u8; CIPHER_BLOCK_SIZE], Option<[u8; CIPHER_BLOCK_SIZE]>), LeMerkTreeError> {
+        match self.get_proof_node(virtual_node, node)? {
+            Some(pair) => {
+                let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+                hash_with_pair::<CIPHER_BLOCK_SIZE, H>(virtual_node, node, &pair, &mut output);
+                Ok((output, Some(pair)))
+            },
+            None => Ok((*node, None)),
+        }
+    }
     pub fn get_cipher_block_by_index(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
-        let flat_tree_index = index.to_flat_hash_tree_index(self).ok_or(IndexError::IndexOverflow)?;
-        Ok(self.flat_hash_tree.get_cipher_block(flat_tree_index.into())?)
+        let flat_tree_index = index.to_flat_hash_tree_index(self).ok_or(LeMerkTreeError::Overflow { index: index.get_index(), max_index: self.max_index.get_index() })?;
+        Ok(self.flat_hash_tree.get(flat_tree_index.into())?)
+    }
+    /// Mutable access to the flat hash tree, for raw writes by flat hash tree index.
+    /// Ancestors of the written nodes aren't updated, recalculate has to be called afterwards.
+    pub fn get_flat_hash_tree_mut(&mut self) -> &mut S {
+        &mut self.flat_hash_tree
     }
     pub fn get_root_data(&self) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
-        Ok(self.flat_hash_tree.get_cipher_block(self.max_index)?)
+        Ok(self.flat_hash_tree.get(self.max_index)?)
     }
-    /// Calculates the node's values in place.
-    fn recalculate(&mut self, index:Index) {
-        todo!()
+    /// Rehashes in place the whole subtree rooted at a node, then the path from the node to the root, from the stored data.
+    /// It's meant to be called after raw writes to the flat hash tree, e.g. through get_flat_hash_tree_mut,
+    /// or to repair a tree from untrusted storage with the root Index. It returns the root update.
+    pub fn recalculate(&mut self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
+        if index > self.max_index { return Err(LeMerkTreeError::Overflow { index: index.get_index(), max_index: self.max_index.get_index() }); };
+        let depth_offset = DepthOffset::try_from(index)?;
+        let mut depth = self.max_depth;
+        while depth > depth_offset.get_depth() {
+            depth -= 1;
+            let subtree_level_length = 1_usize.checked_shl((depth - depth_offset.get_depth()) as u32).ok_or(LeMerkTreeError::BadPow(depth - depth_offset.get_depth()))?;
+            let initial_offset = depth_offset.get_offset().checked_mul(subtree_level_length).ok_or(LeMerkTreeError::BadMultiplication(depth_offset.get_offset()))?;
+            for offset in initial_offset..initial_offset + subtree_level_length {
+                let virtual_node = self.get_virtual_node_by_index(Index::try_from(DepthOffset::from((depth, offset)))?)?;
+                if self.is_empty_node(virtual_node.get_index())? { break; }; // Leaves are packed to the left, so are nodes.
+                self.rehash_node(&virtual_node)?;
+            }
+        };
+        self.recalculate_ancestors([index])
+    }
+    /// Recomputes every ancestor of the given nodes exactly once and returns the root.
+    /// Deeper nodes have greater indexes, so visiting the dirty set from its greatest index goes level by level, bottom-up.
+    fn recalculate_ancestors<I: IntoIterator<Item = Index>>(&mut self, indexes: I) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
+        let mut dirty: BTreeSet<Index> = BTreeSet::new();
+        for index in indexes {
+            if let Some(ancestor_index) = self.get_virtual_node_by_index(index)?.get_ancestor_index()? {
+                dirty.insert(ancestor_index);
+            };
+        }
+        while let Some(index) = dirty.pop_last() {
+            let virtual_node = self.get_virtual_node_by_index(index)?;
+            self.rehash_node(&virtual_node)?;
+            if let Some(ancestor_index) = virtual_node.get_ancestor_index()? {
+                dirty.insert(ancestor_index);
+            };
+        }
+        self.get_root_data()
+    }
+    /// Recomputes an internal node from its successors, according to the odd node policy.
+    /// Empty nodes are left untouched.
+    fn rehash_node(&mut self, virtual_node: &VirtualNode<CIPHER_BLOCK_SIZE>) -> Result<(), LeMerkTreeError> {
+        if self.is_empty_node(virtual_node.get_index())? { return Ok(()); };
+        let data = self.compute_node(virtual_node)?;
+        self.flat_hash_tree.put(virtual_node.get_flat_tree_index().into(), data)?;
+        Ok(())
+    }
+    /// Computes an internal node from its stored successors, according to the odd node policy.
+    fn compute_node(&self, virtual_node: &VirtualNode<CIPHER_BLOCK_SIZE>) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkTreeError> {
+        let (left_successor, _) = virtual_node.get_successors_indexes();
+        let left_virtual_node = self.get_virtual_node_by_index(left_successor.ok_or(LeMerkTreeError::IsNone("successor of a leaf"))?)?;
+        let left_data = self.flat_hash_tree.get(left_virtual_node.get_flat_tree_index().into())?;
+        let (data, _) = self.hash_to_ancestor(&left_virtual_node, &left_data)?;
+        Ok(data)
+    }
+    /// Compares an internal node to the one computed from its stored successors.
+    fn check_node(&self, virtual_node: &VirtualNode<CIPHER_BLOCK_SIZE>) -> Result<Option<NodeMismatch<CIPHER_BLOCK_SIZE>>, LeMerkTreeError> {
+        let expected = self.compute_node(virtual_node)?;
+        let stored = self.flat_hash_tree.get(virtual_node.get_flat_tree_index().into())?;
+        Ok((expected != stored).then(|| NodeMismatch::new(virtual_node.get_index(), expected, stored)))
     }
 }
 
-impl<const CIPHER_BLOCK_SIZE: usize> SizedTree for &LeMerkTree<CIPHER_BLOCK_SIZE> {
+/// Largest power of two strictly lower than size, the RFC 6962 split of size leaves.
+fn largest_power_of_two_below(size: usize) -> usize {
+    if size <= 1 { 0 } else { 1 << (size - 1).ilog2() }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H, S> SizedTree for &LeMerkTree<CIPHER_BLOCK_SIZE, H, S> {
     fn get_max_index(&self) -> usize {
         self.max_index.get_index()
     }
@@ -547,7 +946,7 @@ fn merkletree_depth_20_levels_0_1() {
         [244_u8, 96, 234, 249, 100, 250, 60, 212, 18, 150, 230, 14, 253, 191, 109, 215, 223, 84, 156, 120, 12, 63, 185, 67, 46, 23, 132, 168, 157, 248, 67, 2],
     );
     let mut output = [0_u8; SIZE];
-    hash_visit::<sha3::Sha3_256>(&left, &right, &mut output);
+    hash_visit::<sha3::Sha3_256, _>(&left, &right, &mut output);
     assert_eq!(
         output,
         hex!("d4490f4d374ca8a44685fe9471c5b8dbe58cdffd13d30d9aba15dd29efb92930")
@@ -586,21 +985,21 @@ fn get_last_and_pre_last_levels_from_tree_depth_20() {
 #[should_panic]
 fn get_level_by_depth_index_greater_than_max_depth_index_should_fail() {
     const SIZE: usize = 32;
-    let mut builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
-    let mut tree: LeMerkTree<SIZE> = builder
+    let builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
+    let tree: LeMerkTree<SIZE> = builder
         .with_depth_length(20)
         .with_initial_block(hex!("abababababababababababababababababababababababababababababababab"))
         .try_build::<sha3::Sha3_256>()
         .expect("Unexpected build.");
-    let mut level_20 = tree.get_level_by_depth_index(20).unwrap();  // Max depth index for a LeMerkTree of depth length K is (K - 1).
+    let _level_20 = tree.get_level_by_depth_index(20).unwrap();  // Max depth index for a LeMerkTree of depth length K is (K - 1).
 }
 
 #[test]
 fn examine_virtual_nodes_for_tree_depth_length_20() {
     const SIZE: usize = 32;
     let tree_depth_length = 20;
-    let mut builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
-    let mut tree: LeMerkTree<SIZE> = builder
+    let builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
+    let tree: LeMerkTree<SIZE> = builder
         .with_depth_length(tree_depth_length)
         .with_initial_block([0_u8; SIZE])
         .try_build::<sha3::Sha3_256>()
@@ -611,8 +1010,8 @@ fn examine_virtual_nodes_for_tree_depth_length_20() {
                 let virtual_node = tree.get_virtual_node_by_index(Index::from(node_index)).unwrap();
                 let virtual_node_ancestor = tree.get_virtual_node_by_index(virtual_node.get_ancestor_index().unwrap().unwrap()).unwrap();
                 let (left_successor_index, right_successor_index) = virtual_node.get_successors_indexes();
-                let virtual_node_left_successor = tree.get_virtual_node_by_index(left_successor_index.unwrap().into()).unwrap();
-                let virtual_node_right_successor = tree.get_virtual_node_by_index(right_successor_index.unwrap().into()).unwrap();
+                let virtual_node_left_successor = tree.get_virtual_node_by_index(left_successor_index.unwrap()).unwrap();
+                let virtual_node_right_successor = tree.get_virtual_node_by_index(right_successor_index.unwrap()).unwrap();
                 assert!(virtual_node_ancestor.is_sucessor(&virtual_node));
                 assert!(virtual_node.is_ancestor(&virtual_node_ancestor));
                 assert_ne!(virtual_node_left_successor, virtual_node_right_successor);
@@ -630,8 +1029,8 @@ fn examine_virtual_nodes_for_tree_depth_length_20() {
         let virtual_node = tree.get_virtual_node_by_index(Index::from(0)).unwrap();
         assert_eq!(virtual_node.get_ancestor_index(), Ok(None));
         let (left_successor_index, right_successor_index) = virtual_node.get_successors_indexes();
-        let virtual_node_left_successor = tree.get_virtual_node_by_index(left_successor_index.unwrap().into()).unwrap();
-        let virtual_node_right_successor = tree.get_virtual_node_by_index(right_successor_index.unwrap().into()).unwrap();
+        let virtual_node_left_successor = tree.get_virtual_node_by_index(left_successor_index.unwrap()).unwrap();
+        let virtual_node_right_successor = tree.get_virtual_node_by_index(right_successor_index.unwrap()).unwrap();
         assert_ne!(virtual_node_left_successor, virtual_node_right_successor);
         assert_eq!(virtual_node_left_successor.get_ancestor_index(), virtual_node_right_successor.get_ancestor_index());
         assert!(virtual_node_left_successor.is_ancestor(&virtual_node));
@@ -645,8 +1044,8 @@ fn examine_virtual_nodes_for_tree_depth_length_20() {
 fn leaf_of_one_node_tree() {
     let tree_depth_length = 1;
     const SIZE: usize = 32;
-    let mut builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
-    let mut tree: LeMerkTree<SIZE> = builder
+    let builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
+    let _tree: LeMerkTree<SIZE> = builder
         .with_depth_length(tree_depth_length)
         .with_initial_block([0_u8; SIZE])
         .try_build::<sha3::Sha3_256>()
@@ -659,7 +1058,7 @@ fn building_tree_length_0_should_panic() {
     let tree_depth_length = 0;
     const SIZE: usize = 32;
     let builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
-    let tree: LeMerkTree<SIZE> = builder
+    let _tree: LeMerkTree<SIZE> = builder
         .with_depth_length(tree_depth_length)
         .with_initial_block([0_u8; SIZE])
         .try_build::<sha3::Sha3_256>()
@@ -711,13 +1110,12 @@ fn examine_leaves_for_merkletree_depth_20() {
     let leaves = tree.get_leaves_indexes();
     assert_eq!(leaves.len(), 2_usize.pow(max_depth as u32)); // size of leaves layer is 2_usize.pow(max_depth as u32).
     let paths: Vec<Vec<Index>> = leaves.into_iter()
-        .map(
-            |index| {
+        .inspect(
+            |&index| {
                 // Checks all leaves conform to the initial value and not to a different value.
                 let cipher_block = tree.get_cipher_block_by_index(index).unwrap();
                 assert_eq!(cipher_block, custom_block);  // Test against a custom block.
                 assert_ne!(cipher_block, different_custom_block);
-                index
             }
         )
         .map(
@@ -728,7 +1126,7 @@ fn examine_leaves_for_merkletree_depth_20() {
         .collect();
     let are_different: bool = paths.iter().enumerate().fold((true, &vec![Index::from(0)]), | acc: (bool, &Vec<Index>), (i, path)| {
         assert_ne!(acc.1, path, "non equal {}", i);
-        assert_eq!(acc.1.last().unwrap(), path.last().unwrap()); /// all paths conform to root.
+        assert_eq!(acc.1.last().unwrap(), path.last().unwrap()); // all paths conform to root.
         (acc.0 && path != acc.1, path)
     }).0;
     assert!(are_different);
@@ -761,12 +1159,14 @@ fn examine_leaves_for_merkletree_depth_20() {
 }
 
 //#[test]
+#[cfg(test)]
+#[allow(dead_code)]
 fn verify_paths_for_merkletree_depth_20() {
     const SIZE: usize = 32;
     let max_depth = 19;
     let builder: builder::LeMerkBuilder<SIZE> = builder::LeMerkBuilder::<SIZE>::new();
     let custom_block = hex!("abababababababababababababababababababababababababababababababab");
-    let different_custom_block = hex!("ababababababaffbabababababababababababababababababababababababab");
+    let _different_custom_block = hex!("ababababababaffbabababababababababababababababababababababababab");
     let tree: LeMerkTree<SIZE> = builder
         .with_max_depth(max_depth)
         .with_initial_block(custom_block)  // A custom block.
@@ -777,7 +1177,7 @@ fn verify_paths_for_merkletree_depth_20() {
         .take(15)
         .for_each(
             |x| {
-                let verified = tree.verify_path_to_root_by_index(*x).unwrap();
+                let verified = tree.verify_path_to_root_by_index(*x).unwrap().get_verified_root().unwrap();
                 assert_eq!(verified, tree.get_root_data().unwrap())
             }
         );
@@ -803,7 +1203,7 @@ fn set_and_update_merkletree_depth_20() {
         .for_each(
             |x| {
                 let updated_root = tree.set_and_update(x, different_custom_block).unwrap();
-                let verified = tree.verify_path_to_root_by_index(x).unwrap();
+                let verified = tree.verify_path_to_root_by_index(x).unwrap().get_verified_root().unwrap();
                 assert_eq!(verified, tree.get_root_data().unwrap());
                 assert_eq!(verified, updated_root);
             }
@@ -831,7 +1231,7 @@ fn set_and_update_last_15_merkletree_depth_20() {
         .for_each(
             |x| {
                 let _ = tree.set_and_update(x, different_custom_block);
-                let verified = tree.verify_path_to_root_by_index(x).unwrap();
+                let verified = tree.verify_path_to_root_by_index(x).unwrap().get_verified_root().unwrap();
                 assert_eq!(verified, tree.get_root_data().unwrap())
             }
         );
@@ -861,24 +1261,242 @@ fn set_verify_merkletree_depth_20() {
                 let (new_root, proof) = tree.generate_proof(x).unwrap();
                 assert_eq!(updated_proof, proof);
                 assert_eq!(updated_root, new_root);
-                let mut i = 0;
                 let mut visited = tree.get_cipher_block_by_index(x).unwrap();
-                for node in proof {
+                for node in proof.get_siblings().iter().map(|sibling| sibling.get_block()) {
                     if let Some(ancestor_index) = virtual_node.get_ancestor_index().unwrap() {
                         let ancestor_data = tree.get_cipher_block_by_index(ancestor_index).unwrap();
                         let mut output = [0_u8; SIZE];
-                        hash_visit::<sha3::Sha3_256>(&visited, &node, &mut output);
+                        if virtual_node.is_left_successor() {
+                            hash_visit::<sha3::Sha3_256, _>(&visited, &node, &mut output);
+                        } else {
+                            hash_visit::<sha3::Sha3_256, _>(&node, &visited, &mut output);
+                        }
                         assert_eq!(ancestor_data, output);
                         visited = output;
                         virtual_node = tree.get_virtual_node_by_index(ancestor_index).unwrap();
-                        i+=1;
                     } else {
                         assert_eq!(visited, tree.get_root_data().unwrap());
                     };
                 }
-                let verified = tree.verify_path_to_root_by_index(x).unwrap();
+                let verified = tree.verify_path_to_root_by_index(x).unwrap().get_verified_root().unwrap();
                 assert_eq!(verified, tree.get_root_data().unwrap());
             }
         );
     assert_ne!(tree.get_root_data(), original_root_data);
-}
\ No newline at end of file
+}
+#[test]
+fn set_and_update_keeps_the_builder_digest() {
+    const SIZE: usize = 32;
+    let custom_block = hex!("abababababababababababababababababababababababababababababababab");
+    let different_custom_block = hex!("ababababababaffbabababababababababababababababababababababababab");
+    let mut tree: LeMerkTree<SIZE, sha3::Keccak256> = builder::LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(2)
+        .with_initial_block(custom_block)
+        .try_build::<sha3::Keccak256>()
+        .expect("Unexpected build.");
+    let leaf_index = tree.get_leaves_indexes()[0];
+    let updated_root = tree.set_and_update(leaf_index, different_custom_block).unwrap();
+    let mut level: LeMerkLevel<SIZE, sha3::Keccak256> = LeMerkLevel::from(vec![different_custom_block, custom_block, custom_block, custom_block]);
+    let mut next_level = level.next().unwrap();
+    let keccak_root = next_level.next().unwrap().get_cipher_block(Index::from(0)).unwrap();
+    assert_eq!(updated_root, keccak_root);
+    assert_eq!(tree.verify_path_to_root_by_index(leaf_index).unwrap().get_verified_root().unwrap(), keccak_root);
+    let mut sha3_level: LeMerkLevel<SIZE> = LeMerkLevel::from(vec![different_custom_block, custom_block, custom_block, custom_block]);
+    let mut sha3_next_level = sha3_level.next().unwrap();
+    assert_ne!(updated_root, sha3_next_level.next().unwrap().get_cipher_block(Index::from(0)).unwrap());
+}
+
+#[test]
+fn set_and_update_right_successor_matches_level_hashing() {
+    const SIZE: usize = 32;
+    let custom_block = hex!("abababababababababababababababababababababababababababababababab");
+    let different_custom_block = hex!("ababababababaffbabababababababababababababababababababababababab");
+    let mut tree: LeMerkTree<SIZE> = builder::LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(2)
+        .with_initial_block(custom_block)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    let leaf_index = tree.get_leaves_indexes()[3];
+    assert!(!tree.get_virtual_node_by_index(leaf_index).unwrap().is_left_successor());
+    let updated_root = tree.set_and_update(leaf_index, different_custom_block).unwrap();
+    let mut level: LeMerkLevel<SIZE> = LeMerkLevel::from(vec![custom_block, custom_block, custom_block, different_custom_block]);
+    let mut next_level = level.next().unwrap();
+    assert_eq!(updated_root, next_level.next().unwrap().get_cipher_block(Index::from(0)).unwrap());
+    let (_, proof) = tree.generate_proof(leaf_index).unwrap();
+    assert_eq!(proof.get_siblings()[0].get_side(), Side::Left);
+    assert_eq!(tree.verify_proof(&proof).unwrap(), Some(updated_root));
+}
+
+#[test]
+fn arbitrary_leaf_count_roots_and_proofs() {
+    for (odd_node_policy, mut tree, leaves) in distinct_leaves_trees(1..=17) {
+        let leaf_count = leaves.len();
+        assert_eq!(tree.get_leaves_indexes().len(), leaf_count);
+        let mut level: LeMerkLevel<32> = LeMerkLevel::from(leaves);
+        while let Some(next_level) = level.next_with_policy(odd_node_policy) {
+            level = next_level;
+        }
+        let root = tree.get_root_data().unwrap();
+        assert_eq!(level.get_cipher_block(Index::from(0)).unwrap(), root);
+        assert_eq!(tree.get_level_by_depth_index(0).unwrap(), level);
+        for leaf_index in tree.get_leaves_indexes() {
+            assert_eq!(tree.verify_path_to_root_by_index(leaf_index).unwrap().get_verified_root().unwrap(), root);
+            let (proof_root, proof) = tree.generate_proof(leaf_index).unwrap();
+            assert_eq!(proof_root, root);
+            assert_eq!(tree.verify_proof(&proof).unwrap(), Some(root));
+            if !proof.is_empty() {
+                let truncated_proof = MerkleProof::new(leaf_index, proof.get_depth(), proof.get_siblings()[1..].to_vec());
+                assert_eq!(tree.verify_proof(&truncated_proof).unwrap(), None);
+            }
+        }
+    }
+}
+
+#[test]
+fn one_and_two_leaf_trees_match_every_policy() {
+    for odd_node_policy in ODD_NODE_POLICIES {
+        let (mut tree, leaves) = distinct_leaves_tree(1, odd_node_policy);
+        assert_eq!(tree.get_leaves_indexes(), [Index::from(0)]);
+        assert_eq!(tree.get_root_data().unwrap(), leaves[0]);
+        let (_, proof) = tree.generate_proof(Index::from(0)).unwrap();
+        assert!(proof.is_empty());
+        let (mut tree, leaves) = distinct_leaves_tree(2, odd_node_policy);
+        let mut root = [0_u8; 32];
+        hash_visit::<sha3::Sha3_256, _>(&leaves[0], &leaves[1], &mut root);
+        assert_eq!(tree.get_leaves_indexes(), [Index::from(1), Index::from(2)]);
+        assert_eq!(tree.get_root_data().unwrap(), root);
+        let (_, proof) = tree.generate_proof(tree.get_leaves_indexes()[1]).unwrap();
+        assert_eq!(proof.get_siblings(), [ProofNode::new(Side::Left, leaves[0])]);
+    }
+}
+
+#[test]
+fn duplicate_policy_hashes_lone_nodes_with_themselves() {
+    let (tree, leaves) = distinct_leaves_tree(3, OddNodePolicy::Duplicate);
+    let mut left = [0_u8; 32];
+    let mut right = [0_u8; 32];
+    let mut root = [0_u8; 32];
+    hash_visit::<sha3::Sha3_256, _>(&leaves[0], &leaves[1], &mut left);
+    hash_visit::<sha3::Sha3_256, _>(&leaves[2], &leaves[2], &mut right);
+    hash_visit::<sha3::Sha3_256, _>(&left, &right, &mut root);
+    assert_eq!(tree.get_root_data().unwrap(), root);
+}
+
+#[test]
+fn promote_policy_proofs_skip_empty_pairs() {
+    let (mut tree, leaves) = distinct_leaves_tree(5, OddNodePolicy::Promote);
+    let last_leaf_index = *tree.get_leaves_indexes().last().unwrap();
+    let (_, proof) = tree.generate_proof(last_leaf_index).unwrap();
+    assert_eq!(proof.len(), 1);
+    let mut first_four = LeMerkLevel::<32>::from(leaves[..4].to_vec());
+    let mut next_level = first_four.next().unwrap();
+    assert_eq!(proof.get_siblings()[0], ProofNode::new(Side::Left, next_level.next().unwrap().get_cipher_block(Index::from(0)).unwrap()));
+    let (_, duplicated_proof) = distinct_leaves_tree(5, OddNodePolicy::Duplicate).0.generate_proof(last_leaf_index).unwrap();
+    assert_eq!(duplicated_proof.len(), 3);
+    assert_eq!(duplicated_proof.get_siblings()[0], ProofNode::new(Side::Right, leaves[4]));
+}
+
+#[test]
+fn set_and_update_beyond_leaf_count_is_out_of_bounds() {
+    let (mut tree, _) = distinct_leaves_tree(5, OddNodePolicy::Promote);
+    let empty_leaf_index = Index::try_from((3, 5)).unwrap();
+    assert!(tree.is_empty_node(empty_leaf_index).unwrap());
+    assert_eq!(tree.set_and_update(empty_leaf_index, [0_u8; 32]), Err(LeMerkTreeError::OutOfBounds { index: 5, length: 5 }));
+    assert_eq!(tree.generate_proof(empty_leaf_index), Err(LeMerkTreeError::OutOfBounds { index: 5, length: 5 }));
+}
+
+#[test]
+fn set_many_matches_sequential_updates() {
+    for odd_node_policy in [OddNodePolicy::Promote, OddNodePolicy::Duplicate] {
+        let (mut tree, _) = distinct_leaves_tree(13, odd_node_policy);
+        let (mut sequential_tree, _) = distinct_leaves_tree(13, odd_node_policy);
+        let leaves_indexes = tree.get_leaves_indexes();
+        let updates: Vec<(Index, [u8; 32])> = [0, 3, 4, 12, 3]
+            .iter()
+            .enumerate()
+            .map(|(i, offset)| (leaves_indexes[*offset], [i as u8; 32]))
+            .collect();
+        for (leaf_index, block) in updates.iter() {
+            sequential_tree.set_and_update(*leaf_index, *block).unwrap();
+        }
+        assert_eq!(tree.set_many(&updates).unwrap(), sequential_tree.get_root_data().unwrap());
+        assert_eq!(tree, sequential_tree);
+    }
+}
+
+#[test]
+fn set_many_hashes_each_dirty_ancestor_once() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    static NODE_HASHES: AtomicUsize = AtomicUsize::new(0);
+    struct CountingHasher;
+    impl MerkleHasher for CountingHasher {
+        const OUTPUT_SIZE: usize = 32;
+        const HASHER_ID: u32 = 0x1001;
+        fn hash_leaf(data: &[u8], output: &mut [u8]) {
+            <sha3::Sha3_256 as MerkleHasher>::hash_leaf(data, output);
+        }
+        fn hash_node(left: &[u8], right: &[u8], output: &mut [u8]) {
+            NODE_HASHES.fetch_add(1, Ordering::SeqCst);
+            <sha3::Sha3_256 as MerkleHasher>::hash_node(left, right, output);
+        }
+    }
+    const SIZE: usize = 32;
+    let mut tree: LeMerkTree<SIZE, CountingHasher> = builder::LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(10)
+        .try_build::<CountingHasher>()
+        .expect("Unexpected build.");
+    let updates: Vec<(Index, [u8; SIZE])> = tree.get_leaves_indexes()
+        .into_iter()
+        .take(256)
+        .map(|leaf_index| (leaf_index, [1_u8; SIZE]))
+        .collect();
+    NODE_HASHES.store(0, Ordering::SeqCst);
+    tree.set_many(&updates).unwrap();
+    // 256 leftmost leaves share 128 + 64 + ... + 1 ancestors in their subtree, plus 2 ancestors above it.
+    assert_eq!(NODE_HASHES.load(Ordering::SeqCst), 255 + 2);
+    assert_eq!(tree.verify_path_to_root_by_index(updates[255].0).unwrap().get_verified_root().unwrap(), tree.get_root_data().unwrap());
+}
+
+#[test]
+fn set_many_beyond_leaf_count_writes_nothing() {
+    let (mut tree, _) = distinct_leaves_tree(5, OddNodePolicy::Promote);
+    let (untouched_tree, _) = distinct_leaves_tree(5, OddNodePolicy::Promote);
+    let leaves_indexes = tree.get_leaves_indexes();
+    let beyond_leaf_count = Index::from(leaves_indexes[4].get_index() + 1);
+    assert_eq!(
+        tree.set_many(&[(leaves_indexes[0], [1_u8; 32]), (beyond_leaf_count, [1_u8; 32])]),
+        Err(LeMerkTreeError::OutOfBounds { index: 5, length: 5 })
+    );
+    assert_eq!(tree, untouched_tree);
+}
+
+#[test]
+fn recalculate_subtree_after_raw_writes() {
+    for odd_node_policy in [OddNodePolicy::Promote, OddNodePolicy::Duplicate] {
+        let (mut tree, _) = distinct_leaves_tree(13, odd_node_policy);
+        let (mut updated_tree, _) = distinct_leaves_tree(13, odd_node_policy);
+        let leaves_indexes = tree.get_leaves_indexes();
+        let updates: Vec<(Index, [u8; 32])> = (8..13).map(|offset| (leaves_indexes[offset], [offset as u8; 32])).collect();
+        for (leaf_index, block) in updates.iter() {
+            let flat_tree_index = leaf_index.to_flat_hash_tree_index(&tree).unwrap();
+            tree.get_flat_hash_tree_mut().put(Index::from(flat_tree_index), *block).unwrap();
+        }
+        let subtree_index = Index::try_from(DepthOffset::from((1, 1))).unwrap(); // Ancestor of leaves 8 to 15.
+        assert_eq!(tree.recalculate(subtree_index).unwrap(), updated_tree.set_many(&updates).unwrap());
+        assert_eq!(tree, updated_tree);
+    }
+}
+
+#[test]
+fn recalculate_root_repairs_corrupted_nodes() {
+    let (mut tree, _) = distinct_leaves_tree(11, OddNodePolicy::Promote);
+    let (repaired_tree, _) = distinct_leaves_tree(11, OddNodePolicy::Promote);
+    for corrupted_index in [Index::try_from(DepthOffset::from((2, 1))).unwrap(), Index::try_from(DepthOffset::from((3, 4))).unwrap(), Index::from(0)] {
+        let flat_tree_index = corrupted_index.to_flat_hash_tree_index(&tree).unwrap();
+        tree.get_flat_hash_tree_mut().put(Index::from(flat_tree_index), [0xff_u8; 32]).unwrap();
+    }
+    assert_ne!(tree, repaired_tree);
+    assert_eq!(tree.recalculate(Index::from(0)).unwrap(), repaired_tree.get_root_data().unwrap());
+    assert_eq!(tree, repaired_tree);
+}
+
@@ -0,0 +1,241 @@
+use core::fmt;
+use core::marker::PhantomData;
+use std::fs::{
+    File,
+    OpenOptions,
+};
+use std::path::Path;
+use memmap2::{
+    MmapMut,
+    MmapOptions,
+};
+use crate::{
+    data::{
+        Index,
+        OddNodePolicy,
+    },
+    error::{
+        LeMerkFormatError,
+        LeMerkLevelError,
+        LeMerkStoreError,
+    },
+    format::{
+        HEADER_SIZE,
+        TREE_SECTION_SIZE,
+        TreeHeader,
+        TreeSection,
+    },
+    traits::{
+        MerkleHasher,
+        NodeStore,
+    },
+};
+
+/// Byte offset of the flat node array in a store file.
+const NODES_OFFSET: usize = HEADER_SIZE + TREE_SECTION_SIZE;
+
+/// Node store keeping the flat hash tree in a memory-mapped file, after a TreeHeader and a TreeSection,
+/// so the file is the binary encoding of the tree, as read by LeMerkTree::decode.
+/// Trees larger than the memory are paged in and out by the operating system, and reopening the file restores the tree without rebuilding it.
+/// Every update is flushed to the file unless disabled with set_flush_on_update, a batch of updates being flushed at once.
+pub struct MmapStore<const CIPHER_BLOCK_SIZE: usize, H = sha3::Sha3_256> {
+    header: TreeHeader,
+    tree_section: TreeSection,
+    /// Number of blocks of the flat hash tree.
+    length: usize,
+    map: MmapMut,
+    flush_on_update: bool,
+    hasher: PhantomData<H>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> fmt::Debug for MmapStore<CIPHER_BLOCK_SIZE, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapStore")
+            .field("header", &self.header)
+            .field("tree_section", &self.tree_section)
+            .field("length", &self.length)
+            .field("flush_on_update", &self.flush_on_update)
+            .finish()
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> MmapStore<CIPHER_BLOCK_SIZE, H> {
+    /// Creates or truncates the file at path, holding a zeroed flat hash tree of leaf_count leaves with the odd node policy.
+    pub fn create<P: AsRef<Path>>(path: P, leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<Self, LeMerkStoreError> {
+        let tree_section = TreeSection::new(leaf_count, odd_node_policy);
+        let header = TreeHeader::for_tree::<CIPHER_BLOCK_SIZE, H>(tree_section.get_max_depth()?);
+        let header_bytes = header.to_bytes()?;
+        let encoded_length = Self::get_file_length(&header)?;
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(encoded_length as u64)?;
+        let mut map = Self::map(&file)?;
+        map[..HEADER_SIZE].copy_from_slice(&header_bytes);
+        map[HEADER_SIZE..NODES_OFFSET].copy_from_slice(&tree_section.to_bytes());
+        map.flush_range(0, NODES_OFFSET)?;
+        Ok(
+            MmapStore {
+                header,
+                tree_section,
+                length: header.get_flat_hash_tree_length()?,
+                map,
+                flush_on_update: true,
+                hasher: PhantomData,
+            }
+        )
+    }
+    /// Opens the file at path, checking its header matches CIPHER_BLOCK_SIZE and H, its length the depth of the header,
+    /// and its leaf count the depth too.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LeMerkStoreError> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let map = Self::map(&file)?;
+        let header = TreeHeader::from_bytes(&map)?;
+        header.check::<CIPHER_BLOCK_SIZE, H>()?;
+        let encoded_length = Self::get_file_length(&header)?;
+        if map.len() != encoded_length { return Err(LeMerkFormatError::LengthMismatch { expected: encoded_length, found: map.len() }.into()); };
+        let tree_section = TreeSection::from_bytes(&map[HEADER_SIZE..], header.get_max_depth())?;
+        Ok(
+            MmapStore {
+                header,
+                tree_section,
+                length: header.get_flat_hash_tree_length()?,
+                map,
+                flush_on_update: true,
+                hasher: PhantomData,
+            }
+        )
+    }
+    /// Length in bytes of the header, the tree section and the flat node array.
+    fn get_file_length(header: &TreeHeader) -> Result<usize, LeMerkFormatError> {
+        header.get_encoded_length()?.checked_add(TREE_SECTION_SIZE).ok_or(LeMerkFormatError::Overflow(header.get_max_depth()))
+    }
+    fn map(file: &File) -> Result<MmapMut, LeMerkStoreError> {
+        // Safety: the mapping is owned by the store, the file must not be modified by other processes while it's open.
+        Ok(unsafe { MmapOptions::new().map_mut(file)? })
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> MmapStore<CIPHER_BLOCK_SIZE, H> {
+    pub fn get_header(&self) -> TreeHeader {
+        self.header
+    }
+    pub fn get_max_depth(&self) -> usize {
+        self.header.get_max_depth()
+    }
+    /// Sets whether every put flushes its blocks to the file.
+    pub fn set_flush_on_update(&mut self, flush_on_update: bool) {
+        self.flush_on_update = flush_on_update;
+    }
+    /// Flushes every pending write to the file.
+    pub fn flush(&self) -> Result<(), LeMerkStoreError> {
+        Ok(self.map.flush()?)
+    }
+    /// Byte offset of a block in the file.
+    fn get_offset(&self, index: Index) -> Result<usize, LeMerkLevelError> {
+        let index_usize = index.get_index();
+        if index_usize >= self.length { return Err(LeMerkLevelError::Overflow { index: index_usize, length: self.length }); };
+        Ok(NODES_OFFSET + index_usize * CIPHER_BLOCK_SIZE)
+    }
+    fn write(&mut self, index: Index, block: &[u8; CIPHER_BLOCK_SIZE]) -> Result<usize, LeMerkLevelError> {
+        let offset = self.get_offset(index)?;
+        self.map[offset..offset + CIPHER_BLOCK_SIZE].copy_from_slice(block);
+        Ok(offset)
+    }
+    /// A failing flush leaves the blocks written in memory only, reported as a StoreFailure of the first one.
+    fn flush_bytes(&self, start: usize, end: usize) -> Result<(), LeMerkLevelError> {
+        if !self.flush_on_update { return Ok(()); };
+        self.map.flush_range(start, end - start).map_err(|_| LeMerkLevelError::StoreFailure { index: (start - NODES_OFFSET) / CIPHER_BLOCK_SIZE })
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> NodeStore<CIPHER_BLOCK_SIZE> for MmapStore<CIPHER_BLOCK_SIZE, H> {
+    fn len(&self) -> usize {
+        self.length
+    }
+    fn get(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError> {
+        let offset = self.get_offset(index)?;
+        let mut block = [0_u8; CIPHER_BLOCK_SIZE];
+        block.copy_from_slice(&self.map[offset..offset + CIPHER_BLOCK_SIZE]);
+        Ok(block)
+    }
+    fn put(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<(), LeMerkLevelError> {
+        let offset = self.write(index, &block)?;
+        self.flush_bytes(offset, offset + CIPHER_BLOCK_SIZE)
+    }
+    /// Writes every block, then flushes the span they cover at once.
+    fn put_batch(&mut self, blocks: &[(Index, [u8; CIPHER_BLOCK_SIZE])]) -> Result<(), LeMerkLevelError> {
+        let mut span: Option<(usize, usize)> = None;
+        for (index, block) in blocks {
+            let offset = self.write(*index, block)?;
+            span = Some(match span {
+                Some((start, end)) => (start.min(offset), end.max(offset + CIPHER_BLOCK_SIZE)),
+                None => (offset, offset + CIPHER_BLOCK_SIZE),
+            });
+        }
+        match span {
+            Some((start, end)) => self.flush_bytes(start, end),
+            None => Ok(()),
+        }
+    }
+    fn get_tree_section(&self) -> Option<TreeSection> {
+        Some(self.tree_section)
+    }
+}
+
+#[cfg(test)]
+fn temporary_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(std::format!("lemerk-{}-{}.tree", name, std::process::id()))
+}
+
+#[test]
+fn mmap_store_reopens_updated_tree() {
+    use crate::{LeMerkTree, builder::LeMerkBuilder, error::LeMerkBuilderError};
+    const SIZE: usize = 32;
+    let path = temporary_path("reopen");
+    let builder = LeMerkBuilder::<SIZE>::new().with_max_depth(10).with_initial_block([7_u8; SIZE]);
+    let mut tree: LeMerkTree<SIZE> = builder.try_build::<sha3::Sha3_256>().unwrap();
+    let store: MmapStore<SIZE> = MmapStore::create(&path, 1 << 10, OddNodePolicy::Promote).unwrap();
+    let mut stored_tree: LeMerkTree<SIZE, sha3::Sha3_256, MmapStore<SIZE>> = builder.try_build_with_store(store).unwrap();
+    for leaf_index in tree.get_leaves_indexes().into_iter().step_by(100) {
+        let block = [leaf_index.get_index() as u8; SIZE];
+        assert_eq!(stored_tree.set_update_generate_proof(leaf_index, block).unwrap(), tree.set_update_generate_proof(leaf_index, block).unwrap());
+    }
+    drop(stored_tree);
+    assert_eq!(LeMerkTree::<SIZE>::decode(&std::fs::read(&path).unwrap(), true).unwrap(), tree);
+    let store: MmapStore<SIZE> = MmapStore::open(&path).unwrap();
+    assert_eq!(store.get_max_depth(), 10);
+    assert_eq!(store.get_tree_section(), Some(TreeSection::new(1 << 10, OddNodePolicy::Promote)));
+    let expected = TreeSection::new(1000, OddNodePolicy::Promote);
+    let found = TreeSection::new(1 << 10, OddNodePolicy::Promote);
+    assert!(matches!(builder.clone().with_leaf_count(1000).try_build_from_store::<sha3::Sha3_256, _>(store), Err(LeMerkBuilderError::StoreTreeSectionMismatch { expected: e, found: f }) if e == expected && f == found));
+    let store: MmapStore<SIZE> = MmapStore::open(&path).unwrap();
+    let expected = TreeSection::new(1 << 10, OddNodePolicy::Duplicate);
+    assert!(matches!(builder.clone().with_odd_node_policy(OddNodePolicy::Duplicate).try_build_from_store::<sha3::Sha3_256, _>(store), Err(LeMerkBuilderError::StoreTreeSectionMismatch { expected: e, .. }) if e == expected));
+    let store: MmapStore<SIZE> = MmapStore::open(&path).unwrap();
+    let mut reopened_tree: LeMerkTree<SIZE, sha3::Sha3_256, MmapStore<SIZE>> = builder.try_build_from_store(store).unwrap();
+    assert_eq!(reopened_tree.get_root_data().unwrap(), tree.get_root_data().unwrap());
+    for leaf_index in tree.get_leaves_indexes().into_iter().step_by(37) {
+        assert_eq!(reopened_tree.generate_proof(leaf_index).unwrap(), tree.generate_proof(leaf_index).unwrap());
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn mmap_store_rejects_mismatching_files() {
+    const SIZE: usize = 32;
+    let path = temporary_path("mismatch");
+    assert!(matches!(MmapStore::<SIZE, sha3::Keccak256>::create(&path, 0, OddNodePolicy::Promote), Err(LeMerkStoreError::Format(LeMerkFormatError::BadLeafCount(0)))));
+    let mut store: MmapStore<SIZE, sha3::Keccak256> = MmapStore::create(&path, 4, OddNodePolicy::Promote).unwrap();
+    assert_eq!(NodeStore::len(&store), 7);
+    assert_eq!(store.put(Index::from(7), [1_u8; SIZE]), Err(LeMerkLevelError::Overflow { index: 7, length: 7 }));
+    drop(store);
+    assert!(matches!(MmapStore::<SIZE, sha3::Sha3_256>::open(&path), Err(LeMerkStoreError::Format(LeMerkFormatError::HasherMismatch { .. }))));
+    assert!(matches!(MmapStore::<64, sha3::Sha3_512>::open(&path), Err(LeMerkStoreError::Format(LeMerkFormatError::BlockSizeMismatch { expected: 64, found: 32 }))));
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[HEADER_SIZE] = 5; // A tree of depth 3.
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(matches!(MmapStore::<SIZE, sha3::Keccak256>::open(&path), Err(LeMerkStoreError::Format(LeMerkFormatError::BadLeafCount(5)))));
+    let file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len((NODES_OFFSET + 6 * SIZE) as u64).unwrap();
+    assert!(matches!(MmapStore::<SIZE, sha3::Keccak256>::open(&path), Err(LeMerkStoreError::Format(LeMerkFormatError::LengthMismatch { .. }))));
+    std::fs::remove_file(&path).unwrap();
+}
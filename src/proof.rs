@@ -0,0 +1,837 @@
+use alloc::vec::Vec;
+use crate::{
+    builder::depth_for_leaf_count,
+    crypto::{
+        hash_visit,
+        payload_hash,
+    },
+    data::{
+        Index,
+        DepthOffset,
+        OddNodePolicy,
+    },
+    error::LeMerkTreeError,
+    traits::MerkleHasher,
+};
+#[cfg(test)]
+use alloc::vec;
+#[cfg(test)]
+use crate::{
+    LeMerkTree,
+    test_utils::{
+        ODD_NODE_POLICIES,
+        distinct_leaves_tree,
+        distinct_leaves_trees,
+    },
+};
+
+/// Side taken by a sibling block when it's hashed with the visited node, i.e. Left for left || visited.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A sibling block of a proof, with its side.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ProofNode<const CIPHER_BLOCK_SIZE: usize> {
+    side: Side,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::block"))]
+    block: [u8; CIPHER_BLOCK_SIZE],
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> ProofNode<CIPHER_BLOCK_SIZE> {
+    pub fn new(side: Side, block: [u8; CIPHER_BLOCK_SIZE]) -> Self {
+        ProofNode { side, block }
+    }
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+    pub fn get_block(&self) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.block
+    }
+}
+
+/// Proof of inclusion of a leaf in a LeMerkTree.
+/// The siblings are ordered from the leaf level to the root level. Levels where the leaf's path
+/// is promoted by the odd node policy don't have a sibling, duplicated levels carry the visited node itself.
+/// It's verified without the tree, given the leaf count and odd node policy of the tree, trusted values
+/// known from the same source as the root: they decide which levels are promoted, and can't be taken from the proof.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct MerkleProof<const CIPHER_BLOCK_SIZE: usize> {
+    /// Index of the proven leaf.
+    index: Index,
+    /// Depth of the proven leaf, i.e. the max depth of the tree.
+    depth: usize,
+    siblings: Vec<ProofNode<CIPHER_BLOCK_SIZE>>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> MerkleProof<CIPHER_BLOCK_SIZE> {
+    pub fn new(index: Index, depth: usize, siblings: Vec<ProofNode<CIPHER_BLOCK_SIZE>>) -> Self {
+        MerkleProof { index, depth, siblings }
+    }
+    pub fn get_index(&self) -> Index {
+        self.index
+    }
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+    pub fn get_siblings(&self) -> &[ProofNode<CIPHER_BLOCK_SIZE>] {
+        &self.siblings
+    }
+    pub fn len(&self) -> usize {
+        self.siblings.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.siblings.is_empty()
+    }
+    /// Rebuilds the root from the leaf at the expected index, for a tree of leaf count leaves and odd node policy.
+    /// Every level that isn't promoted takes exactly one sibling on the side of the pair, a duplicated level the visited node itself.
+    /// Returns None if the proof is for another index, or its siblings or depth don't match such a tree.
+    /// It doesn't allocate, so it's usable by no_std verifiers.
+    pub fn compute_root<H: MerkleHasher>(&self, leaf: &[u8; CIPHER_BLOCK_SIZE], index: Index, leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError> {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE { return Err(LeMerkTreeError::HasherOutputSizeMismatch { expected: CIPHER_BLOCK_SIZE, found: H::OUTPUT_SIZE }); };
+        if self.index != index {
+            return Ok(None); // The proof is for another leaf.
+        };
+        let Ok(depth_offset) = DepthOffset::try_from(self.index) else {
+            return Ok(None);
+        };
+        if depth_offset.get_depth() != self.depth || depth_offset.get_offset() >= leaf_count || self.depth != depth_for_leaf_count(leaf_count) {
+            return Ok(None); // The proven leaf isn't in the tree.
+        };
+        let mut offset = depth_offset.get_offset();
+        let mut level_length = leaf_count;
+        let mut siblings = self.siblings.iter();
+        let mut visited = *leaf;
+        for _ in 0..self.depth {
+            let is_lone = offset % 2 == 0 && offset + 1 >= level_length;
+            if !(is_lone && odd_node_policy.promotes()) {
+                let Some(sibling) = siblings.next() else {
+                    return Ok(None); // The proof is shorter than the path to root.
+                };
+                let pair_side = if offset % 2 == 0 { Side::Right } else { Side::Left };
+                if sibling.get_side() != pair_side || (is_lone && sibling.get_block() != visited) {
+                    return Ok(None);
+                };
+                let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+                match pair_side {
+                    Side::Left => hash_visit::<H, _>(&sibling.get_block(), &visited, &mut output),
+                    Side::Right => hash_visit::<H, _>(&visited, &sibling.get_block(), &mut output),
+                };
+                visited = output;
+            };
+            offset /= 2;
+            level_length = level_length.div_ceil(2);
+        }
+        if siblings.next().is_some() {
+            return Ok(None); // The proof is longer than the path to root.
+        };
+        Ok(Some(visited))
+    }
+    /// Verifies the proof for the leaf at index against a known root.
+    pub fn verify<H: MerkleHasher>(&self, root: &[u8; CIPHER_BLOCK_SIZE], leaf: &[u8; CIPHER_BLOCK_SIZE], index: Index, leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<bool, LeMerkTreeError> {
+        Ok(self.compute_root::<H>(leaf, index, leaf_count, odd_node_policy)?.as_ref() == Some(root))
+    }
+    /// Verifies the proof for a leaf set from an arbitrary length payload, hashing it as LeMerkBuilder::with_leaf_data.
+    pub fn verify_data<H: MerkleHasher>(&self, root: &[u8; CIPHER_BLOCK_SIZE], data: &[u8], index: Index, leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<bool, LeMerkTreeError> {
+        let mut leaf = [0_u8; CIPHER_BLOCK_SIZE];
+        payload_hash::<H, _>(data, &mut leaf);
+        self.verify::<H>(root, &leaf, index, leaf_count, odd_node_policy)
+    }
+}
+
+/// Verifies a proof for the leaf at index against a known root, see MerkleProof::verify.
+pub fn verify_proof<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(
+    root: &[u8; CIPHER_BLOCK_SIZE],
+    leaf: &[u8; CIPHER_BLOCK_SIZE],
+    index: Index,
+    leaf_count: usize,
+    odd_node_policy: OddNodePolicy,
+    proof: &MerkleProof<CIPHER_BLOCK_SIZE>,
+) -> Result<bool, LeMerkTreeError> {
+    proof.verify::<H>(root, leaf, index, leaf_count, odd_node_policy)
+}
+
+/// Compact proof of inclusion of a set of leaves in a LeMerkTree.
+/// It carries the minimal set of sibling blocks: nodes computable from the proven leaves, empty pairs and duplicated
+/// nodes are left out. Siblings are ordered level by level from the leaves, and by offset within a level.
+/// As a MerkleProof, it's verified given the trusted leaf count and odd node policy of the tree.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "crate::serialization::MultiProofFields<CIPHER_BLOCK_SIZE>"))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct MultiProof<const CIPHER_BLOCK_SIZE: usize> {
+    /// Indexes of the proven leaves, sorted and without duplicates.
+    indexes: Vec<Index>,
+    /// Depth of the proven leaves, i.e. the max depth of the tree.
+    depth: usize,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::blocks"))]
+    siblings: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> MultiProof<CIPHER_BLOCK_SIZE> {
+    pub fn new(indexes: Vec<Index>, depth: usize, siblings: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> Self {
+        MultiProof { indexes, depth, siblings }
+    }
+    pub fn get_indexes(&self) -> &[Index] {
+        &self.indexes
+    }
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+    pub fn get_siblings(&self) -> &[[u8; CIPHER_BLOCK_SIZE]] {
+        &self.siblings
+    }
+    pub fn len(&self) -> usize {
+        self.siblings.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.siblings.is_empty()
+    }
+    /// Rebuilds the root from the proven leaves, given in the order of the proof indexes, for a tree of leaf count leaves and odd node policy.
+    /// Returns None if the proof is for another depth, or the leaves or the siblings don't match the proof indexes.
+    pub fn compute_root<H: MerkleHasher>(&self, leaves: &[[u8; CIPHER_BLOCK_SIZE]], leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError> {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE { return Err(LeMerkTreeError::HasherOutputSizeMismatch { expected: CIPHER_BLOCK_SIZE, found: H::OUTPUT_SIZE }); };
+        if self.depth != depth_for_leaf_count(leaf_count) {
+            return Ok(None); // The proof is for another tree.
+        };
+        if leaves.len() != self.indexes.len() {
+            return Ok(None);
+        };
+        let mut siblings = self.siblings.iter();
+        let root = fold_multiproof::<CIPHER_BLOCK_SIZE, H, _>(
+            &self.indexes,
+            leaves,
+            self.depth,
+            leaf_count,
+            odd_node_policy,
+            |_| Ok(siblings.next().copied()),
+        )?;
+        if siblings.next().is_some() {
+            return Ok(None); // Unused siblings.
+        };
+        Ok(root)
+    }
+    /// Verifies the proof for a set of leaves, given in the order of the proof indexes, against a known root.
+    pub fn verify<H: MerkleHasher>(&self, root: &[u8; CIPHER_BLOCK_SIZE], leaves: &[[u8; CIPHER_BLOCK_SIZE]], leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<bool, LeMerkTreeError> {
+        Ok(self.compute_root::<H>(leaves, leaf_count, odd_node_policy)?.as_ref() == Some(root))
+    }
+}
+
+/// Verifies a multiproof for a set of leaves against a known root, see MultiProof::verify.
+pub fn verify_multiproof<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(
+    root: &[u8; CIPHER_BLOCK_SIZE],
+    leaves: &[[u8; CIPHER_BLOCK_SIZE]],
+    leaf_count: usize,
+    odd_node_policy: OddNodePolicy,
+    proof: &MultiProof<CIPHER_BLOCK_SIZE>,
+) -> Result<bool, LeMerkTreeError> {
+    proof.verify::<H>(root, leaves, leaf_count, odd_node_policy)
+}
+
+/// Proof that a contiguous range of leaves, from a start to an end leaf both included, is the content of a LeMerkTree there.
+/// Nodes inside the range are computed from the leaves, so it only carries the left siblings of the start leaf's path
+/// and the right siblings of the end leaf's path that fall outside the range, ordered from the leaf level to the root level.
+/// As a MerkleProof, it's verified given the trusted leaf count and odd node policy of the tree.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "crate::serialization::RangeProofFields<CIPHER_BLOCK_SIZE>"))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct RangeProof<const CIPHER_BLOCK_SIZE: usize> {
+    /// First leaf of the range.
+    start: DepthOffset,
+    /// Last leaf of the range.
+    end: DepthOffset,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::blocks"))]
+    left_path: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::blocks"))]
+    right_path: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> RangeProof<CIPHER_BLOCK_SIZE> {
+    pub fn new(start: DepthOffset, end: DepthOffset, left_path: Vec<[u8; CIPHER_BLOCK_SIZE]>, right_path: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> Self {
+        RangeProof { start, end, left_path, right_path }
+    }
+    pub fn get_start(&self) -> DepthOffset {
+        self.start
+    }
+    pub fn get_end(&self) -> DepthOffset {
+        self.end
+    }
+    pub fn get_left_path(&self) -> &[[u8; CIPHER_BLOCK_SIZE]] {
+        &self.left_path
+    }
+    pub fn get_right_path(&self) -> &[[u8; CIPHER_BLOCK_SIZE]] {
+        &self.right_path
+    }
+    /// Rebuilds the root from the leaves of the range, in order, for a tree of leaf count leaves and odd node policy.
+    /// Returns None if the range is at another depth, or the leaves or the boundary paths don't match the range.
+    pub fn compute_root<H: MerkleHasher>(&self, leaves: &[[u8; CIPHER_BLOCK_SIZE]], leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError> {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE { return Err(LeMerkTreeError::HasherOutputSizeMismatch { expected: CIPHER_BLOCK_SIZE, found: H::OUTPUT_SIZE }); };
+        if self.start.get_depth() != depth_for_leaf_count(leaf_count) {
+            return Ok(None); // The proof is for another tree.
+        };
+        let indexes = range_indexes(self.start, self.end)?;
+        if leaves.len() != indexes.len() {
+            return Ok(None);
+        };
+        let mut left_path = self.left_path.iter();
+        let mut right_path = self.right_path.iter();
+        let root = fold_multiproof::<CIPHER_BLOCK_SIZE, H, _>(
+            &indexes,
+            leaves,
+            self.start.get_depth(),
+            leaf_count,
+            odd_node_policy,
+            |depth_offset| {
+                if is_left_of_range(self.start, depth_offset) {
+                    Ok(left_path.next().copied())
+                } else {
+                    Ok(right_path.next().copied())
+                }
+            },
+        )?;
+        if left_path.next().is_some() || right_path.next().is_some() {
+            return Ok(None); // Unused siblings.
+        };
+        Ok(root)
+    }
+    /// Verifies the proof for the leaves of the range, in order, against a known root.
+    pub fn verify<H: MerkleHasher>(&self, root: &[u8; CIPHER_BLOCK_SIZE], leaves: &[[u8; CIPHER_BLOCK_SIZE]], leaf_count: usize, odd_node_policy: OddNodePolicy) -> Result<bool, LeMerkTreeError> {
+        Ok(self.compute_root::<H>(leaves, leaf_count, odd_node_policy)?.as_ref() == Some(root))
+    }
+}
+
+/// Verifies a range proof for the leaves of the range against a known root, see RangeProof::verify.
+pub fn verify_range_proof<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(
+    root: &[u8; CIPHER_BLOCK_SIZE],
+    leaves: &[[u8; CIPHER_BLOCK_SIZE]],
+    leaf_count: usize,
+    odd_node_policy: OddNodePolicy,
+    proof: &RangeProof<CIPHER_BLOCK_SIZE>,
+) -> Result<bool, LeMerkTreeError> {
+    proof.verify::<H>(root, leaves, leaf_count, odd_node_policy)
+}
+
+/// Indexes of the leaves of a range, from the start to the end leaf both included.
+pub(crate) fn range_indexes(start: DepthOffset, end: DepthOffset) -> Result<Vec<Index>, LeMerkTreeError> {
+    if start.get_depth() != end.get_depth() || start.get_offset() > end.get_offset() {
+        return Err(LeMerkTreeError::RuleUnmet("a range ends at the depth of its start, not before it"));
+    };
+    (start.get_offset()..=end.get_offset())
+        .map(|offset| Ok(Index::try_from(DepthOffset::from((start.get_depth(), offset)))?))
+        .collect()
+}
+
+/// Checks if a sibling requested while folding a range is on the left boundary path, i.e. it's left of the range's first node at its depth.
+pub(crate) fn is_left_of_range(start: DepthOffset, sibling: DepthOffset) -> bool {
+    let level_start = start.get_offset() >> (start.get_depth() - sibling.get_depth());
+    sibling.get_offset() < level_start
+}
+
+/// RFC 6962 proof that the tree at an old size is a prefix of the tree at a new size.
+/// The nodes are the subtree roots of the new tree given by the RFC 6962 SUBPROOF algorithm, in the same order.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConsistencyProof<const CIPHER_BLOCK_SIZE: usize> {
+    /// Number of leaves of the old tree.
+    old_size: usize,
+    /// Number of leaves of the new tree.
+    new_size: usize,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::blocks"))]
+    nodes: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> ConsistencyProof<CIPHER_BLOCK_SIZE> {
+    pub fn new(old_size: usize, new_size: usize, nodes: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> Self {
+        ConsistencyProof { old_size, new_size, nodes }
+    }
+    pub fn get_old_size(&self) -> usize {
+        self.old_size
+    }
+    pub fn get_new_size(&self) -> usize {
+        self.new_size
+    }
+    pub fn get_nodes(&self) -> &[[u8; CIPHER_BLOCK_SIZE]] {
+        &self.nodes
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    /// Verifies the proof against the old and new roots, without the trees, following RFC 9162 section 2.1.4.2.
+    /// It doesn't allocate, so it's usable by no_std verifiers.
+    pub fn verify<H: MerkleHasher>(&self, old_root: &[u8; CIPHER_BLOCK_SIZE], new_root: &[u8; CIPHER_BLOCK_SIZE]) -> bool {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE || self.old_size == 0 || self.old_size > self.new_size {
+            return false;
+        };
+        if self.old_size == self.new_size {
+            return self.nodes.is_empty() && old_root == new_root;
+        };
+        // The old root is the first node of the proof when the old tree is a complete subtree of the new one.
+        let mut nodes = core::iter::once(old_root)
+            .filter(|_| self.old_size.is_power_of_two())
+            .chain(self.nodes.iter());
+        let mut old_node = self.old_size - 1;
+        let mut new_node = self.new_size - 1;
+        while old_node & 1 == 1 {
+            old_node >>= 1;
+            new_node >>= 1;
+        }
+        let Some(first) = nodes.next() else { return false; };
+        let mut old_hash = *first;
+        let mut new_hash = *first;
+        for node in nodes {
+            if new_node == 0 {
+                return false;
+            };
+            if old_node & 1 == 1 || old_node == new_node {
+                let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+                hash_visit::<H, _>(node, &old_hash, &mut output);
+                old_hash = output;
+                hash_visit::<H, _>(node, &new_hash, &mut output);
+                new_hash = output;
+                while old_node & 1 == 0 && old_node != 0 {
+                    old_node >>= 1;
+                    new_node >>= 1;
+                }
+            } else {
+                let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+                hash_visit::<H, _>(&new_hash, node, &mut output);
+                new_hash = output;
+            };
+            old_node >>= 1;
+            new_node >>= 1;
+        }
+        new_node == 0 && &old_hash == old_root && &new_hash == new_root
+    }
+}
+
+/// Verifies a consistency proof between a tree of old size and a tree of new size, given their roots, without the trees.
+pub fn verify_consistency<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>(
+    old_root: &[u8; CIPHER_BLOCK_SIZE],
+    old_size: usize,
+    new_root: &[u8; CIPHER_BLOCK_SIZE],
+    new_size: usize,
+    proof: &ConsistencyProof<CIPHER_BLOCK_SIZE>,
+) -> bool {
+    proof.get_old_size() == old_size && proof.get_new_size() == new_size && proof.verify::<H>(old_root, new_root)
+}
+
+/// Hashes a set of sorted leaves up to the root, level by level, asking for the sibling blocks it can't compute.
+/// It's shared by multiproof generation, where siblings are read from the tree, and verification, where they're read from the proof.
+/// Returns None if a sibling is missing or a leaf index is out of the tree.
+pub(crate) fn fold_multiproof<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher, F>(
+    indexes: &[Index],
+    leaves: &[[u8; CIPHER_BLOCK_SIZE]],
+    depth: usize,
+    leaf_count: usize,
+    odd_node_policy: OddNodePolicy,
+    mut get_sibling: F,
+) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError>
+where
+    F: FnMut(DepthOffset) -> Result<Option<[u8; CIPHER_BLOCK_SIZE]>, LeMerkTreeError>,
+{
+    let mut known: Vec<(usize, [u8; CIPHER_BLOCK_SIZE])> = Vec::with_capacity(indexes.len());
+    for (index, leaf) in indexes.iter().zip(leaves.iter()) {
+        let depth_offset = DepthOffset::try_from(*index)?;
+        if depth_offset.get_depth() != depth {
+            return Err(LeMerkTreeError::RuleUnmet("proven indexes are leaves at the proof depth"));
+        };
+        if depth_offset.get_offset() >= leaf_count || known.last().is_some_and(|(offset, _)| *offset >= depth_offset.get_offset()) {
+            return Ok(None); // Leaves must be in the tree, sorted and unique.
+        };
+        known.push((depth_offset.get_offset(), *leaf));
+    }
+    if known.is_empty() {
+        return Ok(None);
+    };
+    let mut level_depth = depth;
+    let mut level_length = leaf_count;
+    while level_depth > 0 {
+        let mut next_known = Vec::with_capacity(known.len());
+        let mut nodes = known.iter().peekable();
+        while let Some((offset, block)) = nodes.next() {
+            let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+            if offset % 2 == 0 {
+                let pair_offset = offset + 1;
+                if let Some((_, pair)) = nodes.next_if(|(next_offset, _)| *next_offset == pair_offset) {
+                    hash_visit::<H, _>(block, pair, &mut output);
+                } else if pair_offset >= level_length {
+                    if odd_node_policy.promotes() {
+                        output = *block;
+                    } else {
+                        hash_visit::<H, _>(block, block, &mut output);
+                    };
+                } else {
+                    match get_sibling(DepthOffset::from((level_depth, pair_offset)))? {
+                        Some(pair) => hash_visit::<H, _>(block, &pair, &mut output),
+                        None => return Ok(None),
+                    };
+                };
+            } else {
+                match get_sibling(DepthOffset::from((level_depth, offset - 1)))? {
+                    Some(pair) => hash_visit::<H, _>(&pair, block, &mut output),
+                    None => return Ok(None),
+                };
+            };
+            next_known.push((offset / 2, output));
+        }
+        known = next_known;
+        level_depth -= 1;
+        level_length = level_length.div_ceil(2);
+    };
+    Ok(known.first().map(|(_, root)| *root))
+}
+
+#[test]
+fn stateless_verification_of_full_tree_proofs() {
+    const SIZE: usize = 32;
+    let different_custom_block = [7_u8; SIZE];
+    let mut tree: LeMerkTree<SIZE> = crate::builder::LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(4)
+        .with_initial_block([1_u8; SIZE])
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    for leaf_index in tree.get_leaves_indexes() {
+        let (root, proof) = tree.set_update_generate_proof(leaf_index, different_custom_block).unwrap();
+        assert_eq!(proof.get_index(), leaf_index);
+        assert_eq!(proof.get_depth(), 4);
+        assert_eq!(proof.len(), 4);
+        assert!(verify_proof::<SIZE, sha3::Sha3_256>(&root, &different_custom_block, leaf_index, 16, OddNodePolicy::Promote, &proof).unwrap());
+        assert!(!verify_proof::<SIZE, sha3::Sha3_256>(&root, &[1_u8; SIZE], leaf_index, 16, OddNodePolicy::Promote, &proof).unwrap());
+        assert!(!verify_proof::<SIZE, sha3::Keccak256>(&root, &different_custom_block, leaf_index, 16, OddNodePolicy::Promote, &proof).unwrap());
+        let truncated_proof = MerkleProof::new(leaf_index, 4, proof.get_siblings()[1..].to_vec());
+        assert!(!verify_proof::<SIZE, sha3::Sha3_256>(&root, &different_custom_block, leaf_index, 16, OddNodePolicy::Promote, &truncated_proof).unwrap());
+    }
+}
+
+#[test]
+fn internal_nodes_cant_be_proven_as_leaves() {
+    let (mut tree, leaves) = distinct_leaves_tree(4, OddNodePolicy::Promote);
+    let root = tree.get_root_data().unwrap();
+    let mut left = [0_u8; 32];
+    let mut right = [0_u8; 32];
+    hash_visit::<sha3::Sha3_256, _>(&leaves[0], &leaves[1], &mut left);
+    hash_visit::<sha3::Sha3_256, _>(&leaves[2], &leaves[3], &mut right);
+    // H(l0 || l1) claimed as the first leaf, its level above being taken for a promoted one.
+    let forged_proof = MerkleProof::new(Index::from(3), 2, vec![ProofNode::new(Side::Right, right)]);
+    for odd_node_policy in ODD_NODE_POLICIES {
+        assert!(!verify_proof::<32, sha3::Sha3_256>(&root, &left, Index::from(3), 4, odd_node_policy, &forged_proof).unwrap());
+    }
+    // The same internal node claimed as the only leaf of a smaller tree.
+    let root_proof = MerkleProof::new(Index::from(1), 1, vec![ProofNode::new(Side::Right, right)]);
+    assert!(!verify_proof::<32, sha3::Sha3_256>(&root, &left, Index::from(1), 4, OddNodePolicy::Promote, &root_proof).unwrap());
+    let (_, proof) = tree.generate_proof(Index::from(3)).unwrap();
+    assert!(verify_proof::<32, sha3::Sha3_256>(&root, &leaves[0], Index::from(3), 4, OddNodePolicy::Promote, &proof).unwrap());
+    let mut extended_siblings = proof.get_siblings().to_vec();
+    extended_siblings.push(ProofNode::new(Side::Right, right));
+    assert!(!verify_proof::<32, sha3::Sha3_256>(&root, &leaves[0], Index::from(3), 4, OddNodePolicy::Promote, &MerkleProof::new(Index::from(3), 2, extended_siblings)).unwrap());
+}
+
+#[test]
+fn proofs_are_bound_to_the_trusted_leaf_count_and_policy() {
+    let (mut tree, leaves) = distinct_leaves_tree(5, OddNodePolicy::Promote);
+    let root = tree.get_root_data().unwrap();
+    let last_leaf_index = *tree.get_leaves_indexes().last().unwrap();
+    let (_, proof) = tree.generate_proof(last_leaf_index).unwrap();
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &leaves[4], last_leaf_index, 5, OddNodePolicy::Promote).unwrap());
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &leaves[4], last_leaf_index, 5, OddNodePolicy::Rfc6962).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[4], last_leaf_index, 5, OddNodePolicy::Duplicate).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[4], last_leaf_index, 4, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[4], last_leaf_index, 6, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[4], last_leaf_index, 0, OddNodePolicy::Promote).unwrap());
+    let (mut duplicated_tree, _) = distinct_leaves_tree(5, OddNodePolicy::Duplicate);
+    let duplicated_root = duplicated_tree.get_root_data().unwrap();
+    let (_, duplicated_proof) = duplicated_tree.generate_proof(last_leaf_index).unwrap();
+    assert!(duplicated_proof.verify::<sha3::Sha3_256>(&duplicated_root, &leaves[4], last_leaf_index, 5, OddNodePolicy::Duplicate).unwrap());
+    let mut forged_siblings = duplicated_proof.get_siblings().to_vec();
+    forged_siblings[0] = ProofNode::new(Side::Right, leaves[3]); // A lone node is only hashed with itself.
+    assert!(!MerkleProof::new(last_leaf_index, 3, forged_siblings).verify::<sha3::Sha3_256>(&duplicated_root, &leaves[4], last_leaf_index, 5, OddNodePolicy::Duplicate).unwrap());
+}
+
+#[test]
+fn siblings_sides_follow_the_leaf_position() {
+    let (mut tree, _) = distinct_leaves_tree(8, OddNodePolicy::Promote);
+    let leaf_index = tree.get_leaves_indexes()[5]; // Offset 0b101: right, left, right successor.
+    let (_, proof) = tree.generate_proof(leaf_index).unwrap();
+    let sides: Vec<Side> = proof.get_siblings().iter().map(|sibling| sibling.get_side()).collect();
+    assert_eq!(sides, [Side::Left, Side::Right, Side::Left]);
+    let mut swapped_siblings = proof.get_siblings().to_vec();
+    swapped_siblings[0] = ProofNode::new(Side::Right, swapped_siblings[0].get_block());
+    assert_eq!(MerkleProof::new(leaf_index, 3, swapped_siblings).compute_root::<sha3::Sha3_256>(&[0_u8; 32], leaf_index, 8, OddNodePolicy::Promote).unwrap(), None);
+}
+
+#[test]
+fn stateless_verification_of_arbitrary_leaf_count_proofs() {
+    for (odd_node_policy, mut tree, leaves) in distinct_leaves_trees(1..=17) {
+        let leaf_count = leaves.len();
+        let root = tree.get_root_data().unwrap();
+        for (leaf_index, leaf) in tree.get_leaves_indexes().into_iter().zip(leaves.iter()) {
+            let (_, proof) = tree.generate_proof(leaf_index).unwrap();
+            assert!(proof.verify::<sha3::Sha3_256>(&root, leaf, leaf_index, leaf_count, odd_node_policy).unwrap());
+            for other_leaf in leaves.iter().filter(|other_leaf| *other_leaf != leaf) {
+                assert!(!proof.verify::<sha3::Sha3_256>(&root, other_leaf, leaf_index, leaf_count, odd_node_policy).unwrap());
+            }
+        }
+    }
+}
+
+#[test]
+fn proof_depth_must_match_index() {
+    let leaf_index = Index::try_from((3, 5)).unwrap();
+    assert_eq!(
+        MerkleProof::<32>::new(leaf_index, 4, Vec::new()).compute_root::<sha3::Sha3_256>(&[0_u8; 32], leaf_index, 8, OddNodePolicy::Promote),
+        Ok(None)
+    );
+}
+
+#[test]
+fn proofs_are_bound_to_the_expected_leaf_position() {
+    // Two leaves holding the same block: the proof of one doesn't prove the other.
+    let leaves = vec![[1_u8; 32], [2_u8; 32], [1_u8; 32], [3_u8; 32]];
+    let mut tree: LeMerkTree<32> = crate::builder::LeMerkBuilder::<32>::new().with_leaves(leaves).try_build::<sha3::Sha3_256>().unwrap();
+    let root = tree.get_root_data().unwrap();
+    let leaves_indexes = tree.get_leaves_indexes();
+    let (_, proof) = tree.generate_proof(leaves_indexes[2]).unwrap();
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &[1_u8; 32], leaves_indexes[2], 4, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &[1_u8; 32], leaves_indexes[0], 4, OddNodePolicy::Promote).unwrap());
+    assert!(!verify_proof::<32, sha3::Sha3_256>(&root, &[1_u8; 32], leaves_indexes[0], 4, OddNodePolicy::Promote, &proof).unwrap());
+}
+
+#[test]
+fn proofs_reject_hashers_of_another_output_size() {
+    let (mut tree, leaves) = distinct_leaves_tree(4, OddNodePolicy::Promote);
+    let root = tree.get_root_data().unwrap();
+    let (_, proof) = tree.generate_proof(tree.get_leaves_indexes()[0]).unwrap();
+    let mismatch = Err(LeMerkTreeError::HasherOutputSizeMismatch { expected: 32, found: 64 });
+    assert_eq!(proof.verify::<sha3::Sha3_512>(&root, &leaves[0], tree.get_leaves_indexes()[0], 4, OddNodePolicy::Promote), mismatch);
+    let (_, multiproof) = tree.generate_multiproof(&tree.get_leaves_indexes()[..2]).unwrap();
+    assert_eq!(multiproof.verify::<sha3::Sha3_512>(&root, &leaves[..2], 4, OddNodePolicy::Promote), mismatch);
+}
+
+#[test]
+fn proofs_verify_against_original_payloads() {
+    const SIZE: usize = 32;
+    let payloads: [&[u8]; 5] = [b"alpha", b"beta", b"gamma", b"delta", b"a payload longer than a single cipher block of the tree"];
+    let mut tree: LeMerkTree<SIZE> = crate::builder::LeMerkBuilder::<SIZE>::new()
+        .with_leaf_data(payloads)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    for (leaf_index, payload) in tree.get_leaves_indexes().into_iter().zip(payloads) {
+        let (root, proof) = tree.generate_proof(leaf_index).unwrap();
+        assert!(proof.verify_data::<sha3::Sha3_256>(&root, payload, leaf_index, 5, OddNodePolicy::Promote).unwrap());
+        assert!(!proof.verify_data::<sha3::Sha3_256>(&root, b"forged", leaf_index, 5, OddNodePolicy::Promote).unwrap());
+    }
+    let leaf_index = tree.get_leaves_indexes()[1];
+    let root = tree.set_leaf_data(leaf_index, b"updated beta").unwrap();
+    let (_, proof) = tree.generate_proof(leaf_index).unwrap();
+    assert!(proof.verify_data::<sha3::Sha3_256>(&root, b"updated beta", leaf_index, 5, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify_data::<sha3::Sha3_256>(&root, b"beta", leaf_index, 5, OddNodePolicy::Promote).unwrap());
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn rfc6962_payload_proofs_are_domain_separated() {
+    use crate::crypto::CertificateTransparency;
+    const SIZE: usize = 32;
+    let payloads: [&[u8]; 3] = [b"first", b"second", b"third"];
+    let mut tree: LeMerkTree<SIZE, CertificateTransparency> = crate::builder::LeMerkBuilder::<SIZE>::new()
+        .with_leaf_data(payloads)
+        .with_odd_node_policy(OddNodePolicy::Rfc6962)
+        .try_build::<CertificateTransparency>()
+        .expect("Unexpected build.");
+    let leaf_index = tree.get_leaves_indexes()[2];
+    let (root, proof) = tree.generate_proof(leaf_index).unwrap();
+    assert!(proof.verify_data::<CertificateTransparency>(&root, b"third", leaf_index, 3, OddNodePolicy::Rfc6962).unwrap());
+    let mut undomained_leaf = [0_u8; SIZE];
+    crate::crypto::data_hash::<crate::crypto::Sha256, _>(b"third", &mut undomained_leaf);
+    assert!(!proof.verify::<CertificateTransparency>(&root, &undomained_leaf, leaf_index, 3, OddNodePolicy::Rfc6962).unwrap());
+}
+
+#[test]
+fn multiproofs_verify_sets_of_leaves() {
+    for (odd_node_policy, mut tree, leaves) in distinct_leaves_trees([1, 2, 7, 8, 13, 17]) {
+        let leaf_count = leaves.len();
+        let leaves_indexes = tree.get_leaves_indexes();
+        for step in 1..=3 {
+            let offsets: Vec<usize> = (0..leaf_count).step_by(step).collect();
+            let indexes: Vec<Index> = offsets.iter().map(|offset| leaves_indexes[*offset]).collect();
+            let proven_leaves: Vec<[u8; 32]> = offsets.iter().map(|offset| leaves[*offset]).collect();
+            let (root, proof) = tree.generate_multiproof(&indexes).unwrap();
+            assert_eq!(root, tree.get_root_data().unwrap());
+            assert!(verify_multiproof::<32, sha3::Sha3_256>(&root, &proven_leaves, leaf_count, odd_node_policy, &proof).unwrap());
+            let single_proofs_length: usize = indexes.iter().map(|index| tree.generate_proof(*index).unwrap().1.len()).sum();
+            assert!(proof.len() <= single_proofs_length);
+            let mut forged_leaves = proven_leaves.clone();
+            forged_leaves[0] = [0xff_u8; 32];
+            assert!(!proof.verify::<sha3::Sha3_256>(&root, &forged_leaves, leaf_count, odd_node_policy).unwrap());
+            if !proof.is_empty() {
+                let truncated_proof = MultiProof::new(proof.get_indexes().to_vec(), proof.get_depth(), proof.get_siblings()[1..].to_vec());
+                assert!(!truncated_proof.verify::<sha3::Sha3_256>(&root, &proven_leaves, leaf_count, odd_node_policy).unwrap());
+            }
+        }
+    }
+}
+
+#[test]
+fn multiproofs_are_bound_to_the_trusted_tree_shape() {
+    let (tree, leaves) = distinct_leaves_tree(4, OddNodePolicy::Promote);
+    let root = tree.get_root_data().unwrap();
+    let mut left = [0_u8; 32];
+    let mut right = [0_u8; 32];
+    hash_visit::<sha3::Sha3_256, _>(&leaves[0], &leaves[1], &mut left);
+    hash_visit::<sha3::Sha3_256, _>(&leaves[2], &leaves[3], &mut right);
+    // H(l0 || l1) claimed as the first leaf of a two leaf tree with the same root.
+    let forged_proof = MultiProof::new(vec![Index::from(1)], 1, vec![right]);
+    assert_eq!(forged_proof.compute_root::<sha3::Sha3_256>(&[left], 2, OddNodePolicy::Promote).unwrap(), Some(root));
+    assert!(!forged_proof.verify::<sha3::Sha3_256>(&root, &[left], 4, OddNodePolicy::Promote).unwrap());
+    let (_, proof) = tree.generate_multiproof(&tree.get_leaves_indexes()[3..]).unwrap();
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &leaves[3..], 4, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[3..], 3, OddNodePolicy::Promote).unwrap());
+    let (tree, leaves) = distinct_leaves_tree(3, OddNodePolicy::Duplicate);
+    let (root, proof) = tree.generate_multiproof(&tree.get_leaves_indexes()[2..]).unwrap();
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &leaves[2..], 3, OddNodePolicy::Duplicate).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[2..], 3, OddNodePolicy::Promote).unwrap());
+}
+
+#[test]
+fn multiproofs_leave_out_computable_siblings() {
+    let (tree, leaves) = distinct_leaves_tree(8, OddNodePolicy::Promote);
+    let leaves_indexes = tree.get_leaves_indexes();
+    // Leaves 0 to 3 make the whole left subtree, only the right subtree's root is needed.
+    let (root, proof) = tree.generate_multiproof(&[leaves_indexes[3], leaves_indexes[1], leaves_indexes[0], leaves_indexes[2], leaves_indexes[1]]).unwrap();
+    assert_eq!(proof.get_indexes(), &leaves_indexes[0..4]);
+    assert_eq!(proof.get_siblings(), &[tree.get_cipher_block_by_index(Index::from(2)).unwrap()]);
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &leaves[0..4], 8, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[1..5], 8, OddNodePolicy::Promote).unwrap());
+    assert_eq!(tree.generate_multiproof(&[Index::from(2)]), Err(LeMerkTreeError::OutOfBounds { index: 13, length: 8 }));
+}
+
+#[test]
+fn range_proofs_verify_contiguous_leaves() {
+    for (odd_node_policy, tree, leaves) in distinct_leaves_trees([1, 2, 8, 13]) {
+        let leaf_count = leaves.len();
+        let depth = tree.max_depth;
+        let root = tree.get_root_data().unwrap();
+        for start in 0..leaf_count {
+            for end in start..leaf_count {
+                let (proof_root, proof) = tree.generate_range_proof(DepthOffset::from((depth, start)), DepthOffset::from((depth, end))).unwrap();
+                assert_eq!(proof_root, root);
+                assert!(proof.get_left_path().len() <= depth && proof.get_right_path().len() <= depth);
+                assert!(verify_range_proof::<32, sha3::Sha3_256>(&root, &leaves[start..=end], leaf_count, odd_node_policy, &proof).unwrap());
+                let mut forged_leaves = leaves[start..=end].to_vec();
+                forged_leaves[end - start] = [0xff_u8; 32];
+                assert!(!proof.verify::<sha3::Sha3_256>(&root, &forged_leaves, leaf_count, odd_node_policy).unwrap());
+                if end + 1 < leaf_count {
+                    let shifted_proof = RangeProof::new(
+                        DepthOffset::from((depth, start + 1)),
+                        DepthOffset::from((depth, end + 1)),
+                        proof.get_left_path().to_vec(),
+                        proof.get_right_path().to_vec(),
+                    );
+                    assert!(!shifted_proof.verify::<sha3::Sha3_256>(&root, &leaves[start..=end], leaf_count, odd_node_policy).unwrap());
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn range_proofs_are_bound_to_the_trusted_tree_shape() {
+    let (tree, leaves) = distinct_leaves_tree(4, OddNodePolicy::Promote);
+    let root = tree.get_root_data().unwrap();
+    let mut left = [0_u8; 32];
+    let mut right = [0_u8; 32];
+    hash_visit::<sha3::Sha3_256, _>(&leaves[0], &leaves[1], &mut left);
+    hash_visit::<sha3::Sha3_256, _>(&leaves[2], &leaves[3], &mut right);
+    // H(l0 || l1) claimed as a one leaf range of a two leaf tree with the same root.
+    let forged_proof = RangeProof::new(DepthOffset::from((1, 0)), DepthOffset::from((1, 0)), Vec::new(), vec![right]);
+    assert_eq!(forged_proof.compute_root::<sha3::Sha3_256>(&[left], 2, OddNodePolicy::Promote).unwrap(), Some(root));
+    assert!(!forged_proof.verify::<sha3::Sha3_256>(&root, &[left], 4, OddNodePolicy::Promote).unwrap());
+    let (_, proof) = tree.generate_range_proof(DepthOffset::from((2, 2)), DepthOffset::from((2, 3))).unwrap();
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &leaves[2..], 4, OddNodePolicy::Promote).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[2..], 3, OddNodePolicy::Promote).unwrap());
+    let truncated_proof = RangeProof::new(proof.get_start(), proof.get_end(), Vec::new(), Vec::new());
+    assert!(!truncated_proof.verify::<sha3::Sha3_256>(&root, &leaves[2..], 4, OddNodePolicy::Promote).unwrap());
+    let (tree, leaves) = distinct_leaves_tree(3, OddNodePolicy::Duplicate);
+    let (root, proof) = tree.generate_range_proof(DepthOffset::from((2, 2)), DepthOffset::from((2, 2))).unwrap();
+    assert!(proof.verify::<sha3::Sha3_256>(&root, &leaves[2..], 3, OddNodePolicy::Duplicate).unwrap());
+    assert!(!proof.verify::<sha3::Sha3_256>(&root, &leaves[2..], 3, OddNodePolicy::Promote).unwrap());
+}
+
+#[test]
+fn range_proofs_carry_boundary_paths_only() {
+    let (tree, _) = distinct_leaves_tree(16, OddNodePolicy::Promote);
+    let (_, proof) = tree.generate_range_proof(DepthOffset::from((4, 3)), DepthOffset::from((4, 12))).unwrap();
+    let block = |depth, offset| tree.get_cipher_block_by_index(Index::try_from(DepthOffset::from((depth, offset))).unwrap()).unwrap();
+    assert_eq!(proof.get_left_path(), &[block(4, 2), block(3, 0)]);
+    assert_eq!(proof.get_right_path(), &[block(4, 13), block(3, 7)]);
+    assert_eq!(
+        tree.generate_range_proof(DepthOffset::from((4, 5)), DepthOffset::from((4, 4))),
+        Err(LeMerkTreeError::RuleUnmet("a range ends at the depth of its start, not before it"))
+    );
+    assert_eq!(
+        tree.generate_range_proof(DepthOffset::from((4, 5)), DepthOffset::from((4, 16))),
+        Err(LeMerkTreeError::OutOfBounds { index: 16, length: 16 })
+    );
+}
+
+#[test]
+fn consistency_proofs_between_tree_sizes() {
+    for odd_node_policy in [OddNodePolicy::Promote, OddNodePolicy::Rfc6962] {
+        let (tree, _) = distinct_leaves_tree(17, odd_node_policy);
+        for new_size in 1..=17 {
+            let new_root = tree.get_root_data_by_leaf_count(new_size).unwrap();
+            assert_eq!(new_root, distinct_leaves_tree(new_size, odd_node_policy).0.get_root_data().unwrap());
+            for old_size in 1..=new_size {
+                let old_root = tree.get_root_data_by_leaf_count(old_size).unwrap();
+                let proof = tree.generate_consistency_proof(old_size, new_size).unwrap();
+                assert!(verify_consistency::<32, sha3::Sha3_256>(&old_root, old_size, &new_root, new_size, &proof));
+                if old_size < new_size {
+                    assert!(!verify_consistency::<32, sha3::Sha3_256>(&new_root, old_size, &new_root, new_size, &proof));
+                    assert!(!verify_consistency::<32, sha3::Sha3_256>(&old_root, old_size + 1, &new_root, new_size, &proof));
+                    let mut forged_nodes = proof.get_nodes().to_vec();
+                    forged_nodes[0] = [0xff_u8; 32];
+                    assert!(!ConsistencyProof::new(old_size, new_size, forged_nodes).verify::<sha3::Sha3_256>(&old_root, &new_root));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn consistency_proofs_need_the_promote_shape() {
+    let (tree, _) = distinct_leaves_tree(7, OddNodePolicy::Duplicate);
+    assert_eq!(tree.generate_consistency_proof(3, 7), Err(LeMerkTreeError::RuleUnmet("consistency proofs need a promoting odd node policy")));
+    let (tree, _) = distinct_leaves_tree(7, OddNodePolicy::Promote);
+    assert_eq!(tree.generate_consistency_proof(0, 7), Err(LeMerkTreeError::RuleUnmet("consistency proofs need 0 < old size <= new size")));
+    assert_eq!(tree.generate_consistency_proof(5, 3), Err(LeMerkTreeError::RuleUnmet("consistency proofs need 0 < old size <= new size")));
+    assert_eq!(tree.generate_consistency_proof(3, 8), Err(LeMerkTreeError::OutOfBounds { index: 8, length: 7 }));
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn rfc6962_consistency_proofs_match_test_roots() {
+    use crate::crypto::{CertificateTransparency, RFC6962_TEST_LEAVES, RFC6962_TEST_ROOTS};
+    let tree: LeMerkTree<32, CertificateTransparency> = crate::builder::LeMerkBuilder::<32>::new()
+        .with_leaf_data(RFC6962_TEST_LEAVES)
+        .with_odd_node_policy(OddNodePolicy::Rfc6962)
+        .try_build::<CertificateTransparency>()
+        .expect("Unexpected build.");
+    for new_size in 1..=RFC6962_TEST_ROOTS.len() {
+        for old_size in 1..=new_size {
+            let proof = tree.generate_consistency_proof(old_size, new_size).unwrap();
+            assert!(verify_consistency::<32, CertificateTransparency>(&RFC6962_TEST_ROOTS[old_size - 1], old_size, &RFC6962_TEST_ROOTS[new_size - 1], new_size, &proof));
+        }
+    }
+    // RFC 6962 consistency proof sizes between the 8 leaves tree and its prefixes.
+    let proof_lengths: [usize; 7] = [3, 2, 4, 1, 4, 3, 4];
+    for (old_size, proof_length) in (1..8).zip(proof_lengths) {
+        assert_eq!(tree.generate_consistency_proof(old_size, 8).unwrap().len(), proof_length);
+    }
+}
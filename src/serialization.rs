@@ -0,0 +1,328 @@
+use core::fmt;
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+    de,
+};
+use crate::{
+    LeMerkLevel,
+    LeMerkTree,
+    data::{
+        DepthOffset,
+        Index,
+    },
+    error::LeMerkTreeError,
+    proof::{
+        MultiProof,
+        RangeProof,
+    },
+    traits::{
+        MerkleHasher,
+        NodeStore,
+    },
+};
+
+/// Serde support for blocks: a hex string in human-readable formats, raw bytes otherwise.
+pub(crate) mod block {
+    use super::*;
+
+    pub fn serialize<const CIPHER_BLOCK_SIZE: usize, S: Serializer>(block: &[u8; CIPHER_BLOCK_SIZE], serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(block, serializer)
+    }
+
+    pub fn deserialize<'de, const CIPHER_BLOCK_SIZE: usize, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; CIPHER_BLOCK_SIZE], D::Error> {
+        Block::<CIPHER_BLOCK_SIZE>::deserialize(deserializer).map(|block| block.0)
+    }
+}
+
+/// Serde support for sequences of blocks, each one as in block.
+pub(crate) mod blocks {
+    use super::*;
+
+    pub fn serialize<const CIPHER_BLOCK_SIZE: usize, S: Serializer>(blocks: &[[u8; CIPHER_BLOCK_SIZE]], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(blocks.iter().map(|block| Block(*block)))
+    }
+
+    pub fn deserialize<'de, const CIPHER_BLOCK_SIZE: usize, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<[u8; CIPHER_BLOCK_SIZE]>, D::Error> {
+        Ok(Vec::<Block<CIPHER_BLOCK_SIZE>>::deserialize(deserializer)?.into_iter().map(|block| block.0).collect())
+    }
+}
+
+struct Block<const CIPHER_BLOCK_SIZE: usize>([u8; CIPHER_BLOCK_SIZE]);
+
+impl<const CIPHER_BLOCK_SIZE: usize> Serialize for Block<CIPHER_BLOCK_SIZE> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.0, serializer)
+    }
+}
+
+impl<'de, const CIPHER_BLOCK_SIZE: usize> Deserialize<'de> for Block<CIPHER_BLOCK_SIZE> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut block = [0_u8; CIPHER_BLOCK_SIZE];
+        deserialize_bytes(deserializer, &mut block)?;
+        Ok(Block(block))
+    }
+}
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&to_hex(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+/// Fills output from a hex string, with or without 0x prefix, or from bytes of the same length.
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D, output: &mut [u8]) -> Result<(), D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor(output))
+    } else {
+        deserializer.deserialize_bytes(BytesVisitor(output))
+    }
+}
+
+struct BytesVisitor<'a>(&'a mut [u8]);
+
+impl<'de> de::Visitor<'de> for BytesVisitor<'_> {
+    type Value = ();
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes, or {} hex digits", self.0.len(), self.0.len() * 2)
+    }
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<(), E> {
+        let value = value.strip_prefix("0x").unwrap_or(value);
+        if value.len() != self.0.len() * 2 { return Err(E::invalid_length(value.len(), &self)); };
+        from_hex(value, self.0).ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &"hex digits"))
+    }
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<(), E> {
+        if value.len() != self.0.len() { return Err(E::invalid_length(value.len(), &self)); };
+        self.0.copy_from_slice(value);
+        Ok(())
+    }
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let length = self.0.len();
+        for i in 0..length {
+            self.0[i] = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i, &"a full block"))?;
+        }
+        if seq.next_element::<u8>()?.is_some() { return Err(de::Error::invalid_length(length + 1, &"a full block")); };
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    bytes
+        .iter()
+        .flat_map(|byte| [DIGITS[(byte >> 4) as usize] as char, DIGITS[(byte & 0x0f) as usize] as char])
+        .collect()
+}
+
+fn from_hex(hex: &str, output: &mut [u8]) -> Option<()> {
+    for (byte, pair) in output.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        let high = (pair[0] as char).to_digit(16)?;
+        let low = (pair[1] as char).to_digit(16)?;
+        *byte = (high << 4 | low) as u8;
+    }
+    Some(())
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> Serialize for LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        blocks::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, const CIPHER_BLOCK_SIZE: usize, H> Deserialize<'de> for LeMerkLevel<CIPHER_BLOCK_SIZE, H> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(LeMerkLevel::from(blocks::deserialize(deserializer)?))
+    }
+}
+
+/// Fields of a MultiProof, checked before the proof is deserialized.
+#[derive(Deserialize)]
+pub(crate) struct MultiProofFields<const CIPHER_BLOCK_SIZE: usize> {
+    indexes: Vec<Index>,
+    depth: usize,
+    #[serde(with = "blocks")]
+    siblings: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+}
+
+/// Indexes of a deserialized MultiProof must be sorted and without duplicates, as generated.
+impl<const CIPHER_BLOCK_SIZE: usize> TryFrom<MultiProofFields<CIPHER_BLOCK_SIZE>> for MultiProof<CIPHER_BLOCK_SIZE> {
+    type Error = LeMerkTreeError;
+    fn try_from(fields: MultiProofFields<CIPHER_BLOCK_SIZE>) -> Result<Self, Self::Error> {
+        if !fields.indexes.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(LeMerkTreeError::RuleUnmet("multiproof indexes are sorted, without duplicates"));
+        };
+        Ok(MultiProof::new(fields.indexes, fields.depth, fields.siblings))
+    }
+}
+
+/// Fields of a RangeProof, checked before the proof is deserialized.
+#[derive(Deserialize)]
+pub(crate) struct RangeProofFields<const CIPHER_BLOCK_SIZE: usize> {
+    start: DepthOffset,
+    end: DepthOffset,
+    #[serde(with = "blocks")]
+    left_path: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+    #[serde(with = "blocks")]
+    right_path: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+}
+
+/// The end of a deserialized RangeProof must be at the depth of its start, not before it.
+impl<const CIPHER_BLOCK_SIZE: usize> TryFrom<RangeProofFields<CIPHER_BLOCK_SIZE>> for RangeProof<CIPHER_BLOCK_SIZE> {
+    type Error = LeMerkTreeError;
+    fn try_from(fields: RangeProofFields<CIPHER_BLOCK_SIZE>) -> Result<Self, Self::Error> {
+        if fields.start.get_depth() != fields.end.get_depth() || fields.start.get_offset() > fields.end.get_offset() {
+            return Err(LeMerkTreeError::RuleUnmet("a range ends at the depth of its start, not before it"));
+        };
+        Ok(RangeProof::new(fields.start, fields.end, fields.left_path, fields.right_path))
+    }
+}
+
+/// A tree is serialized as its binary encoding, hex encoded in human-readable formats.
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher, S: NodeStore<CIPHER_BLOCK_SIZE>> Serialize for LeMerkTree<CIPHER_BLOCK_SIZE, H, S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let bytes = self.encode().map_err(serde::ser::Error::custom)?;
+        serialize_bytes(&bytes, serializer)
+    }
+}
+
+/// Deserialization decodes the encoding as LeMerkTree::decode with verify, rehashing every parent.
+impl<'de, const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> Deserialize<'de> for LeMerkTree<CIPHER_BLOCK_SIZE, H> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_tree(deserializer, true)
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> LeMerkTree<CIPHER_BLOCK_SIZE, H> {
+    /// Deserializes a trusted tree as LeMerkTree::decode without verify, only checking the header and lengths.
+    /// It's meant for #[serde(deserialize_with = "LeMerkTree::deserialize_unverified")].
+    pub fn deserialize_unverified<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_tree(deserializer, false)
+    }
+}
+
+fn deserialize_tree<'de, const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher, D: Deserializer<'de>>(deserializer: D, verify: bool) -> Result<LeMerkTree<CIPHER_BLOCK_SIZE, H>, D::Error> {
+    let bytes = if deserializer.is_human_readable() {
+        let hex = String::deserialize(deserializer)?;
+        let hex = hex.strip_prefix("0x").unwrap_or(&hex);
+        if hex.len() % 2 != 0 { return Err(de::Error::invalid_length(hex.len(), &"an even number of hex digits")); };
+        let mut bytes = alloc::vec![0_u8; hex.len() / 2];
+        from_hex(hex, &mut bytes).ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(hex), &"hex digits"))?;
+        bytes
+    } else {
+        deserialize_byte_buf(deserializer)?
+    };
+    LeMerkTree::decode(&bytes, verify).map_err(de::Error::custom)
+}
+
+fn deserialize_byte_buf<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    struct ByteBufVisitor;
+    impl<'de> de::Visitor<'de> for ByteBufVisitor {
+        type Value = Vec<u8>;
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an encoded tree")
+        }
+        fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(value.to_vec())
+        }
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+    deserializer.deserialize_byte_buf(ByteBufVisitor)
+}
+
+#[cfg(test)]
+use crate::{
+    builder::LeMerkBuilder,
+    data::OddNodePolicy,
+    proof::MerkleProof,
+};
+
+#[test]
+fn proof_json_uses_hex_blocks() {
+    const SIZE: usize = 32;
+    let mut tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new().with_max_depth(2).with_initial_block([0xab; SIZE]).try_build::<sha3::Sha3_256>().unwrap();
+    let (_, proof) = tree.generate_proof(Index::from(4)).unwrap();
+    let json = serde_json::to_string(&proof).unwrap();
+    assert!(json.contains(&to_hex(&[0xab; SIZE])));
+    assert!(json.starts_with(r#"{"index":4,"depth":2,"siblings":[{"side":"Left","block":""#));
+    let decoded_proof: MerkleProof<SIZE> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded_proof, proof);
+    let prefixed_json = json.replacen(&to_hex(&[0xab; SIZE]), &alloc::format!("0x{}", to_hex(&[0xab; SIZE])), 1);
+    assert_eq!(serde_json::from_str::<MerkleProof<SIZE>>(&prefixed_json).unwrap(), proof);
+    assert!(serde_json::from_str::<MerkleProof<SIZE>>(&json.replacen("abab", "xyab", 1)).is_err());
+    assert!(serde_json::from_str::<MerkleProof<SIZE>>(&json.replacen("abab", "ab", 1)).is_err());
+}
+
+#[test]
+fn index_and_depth_offset_json() {
+    assert_eq!(serde_json::to_string(&Index::from(6)).unwrap(), "6");
+    assert_eq!(serde_json::to_string(&DepthOffset::from((2, 3))).unwrap(), "[2,3]");
+    assert_eq!(serde_json::from_str::<DepthOffset>("[2,3]").unwrap(), DepthOffset::from((2, 3)));
+    assert_eq!(serde_json::to_string(&OddNodePolicy::Rfc6962).unwrap(), r#""Rfc6962""#);
+}
+
+#[test]
+fn tree_round_trips_in_binary_and_json() {
+    const SIZE: usize = 32;
+    let leaves: Vec<[u8; SIZE]> = (0..5_u8).map(|leaf| [leaf; SIZE]).collect();
+    let tree: LeMerkTree<SIZE, sha3::Keccak256> = LeMerkBuilder::<SIZE>::new().with_leaves(leaves).try_build::<sha3::Keccak256>().unwrap();
+    let bytes = postcard::to_allocvec(&tree).unwrap();
+    assert_eq!(postcard::from_bytes::<LeMerkTree<SIZE, sha3::Keccak256>>(&bytes).unwrap(), tree);
+    assert!(postcard::from_bytes::<LeMerkTree<SIZE>>(&bytes).is_err()); // Hasher mismatch.
+    let json = serde_json::to_string(&tree).unwrap();
+    assert_eq!(serde_json::from_str::<LeMerkTree<SIZE, sha3::Keccak256>>(&json).unwrap(), tree);
+    let level = tree.get_level_by_depth_index(3).unwrap();
+    let level_bytes = postcard::to_allocvec(&level).unwrap();
+    assert_eq!(postcard::from_bytes::<LeMerkLevel<SIZE, sha3::Keccak256>>(&level_bytes).unwrap(), level);
+}
+
+#[test]
+fn tree_deserialization_rehashes_parents_unless_unverified() {
+    const SIZE: usize = 32;
+    let leaves: Vec<[u8; SIZE]> = (0..5_u8).map(|leaf| [leaf; SIZE]).collect();
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new().with_leaves(leaves).try_build::<sha3::Sha3_256>().unwrap();
+    let json = serde_json::to_string(&tree).unwrap();
+    let root = to_hex(&tree.get_root_data().unwrap());
+    let corrupted_json = json.replacen(&root, &to_hex(&[0xab; SIZE]), 1);
+    assert!(serde_json::from_str::<LeMerkTree<SIZE>>(&corrupted_json).is_err());
+    let corrupted_tree = LeMerkTree::<SIZE>::deserialize_unverified(&mut serde_json::Deserializer::from_str(&corrupted_json)).unwrap();
+    assert_eq!(corrupted_tree.get_root_data().unwrap(), [0xab; SIZE]);
+    let mut bytes = postcard::to_allocvec(&tree).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    assert!(postcard::from_bytes::<LeMerkTree<SIZE>>(&bytes).is_err());
+    assert!(LeMerkTree::<SIZE>::deserialize_unverified(&mut postcard::Deserializer::from_bytes(&bytes)).is_ok());
+}
+
+#[test]
+fn multiproof_and_range_proof_json_are_checked() {
+    const SIZE: usize = 32;
+    let leaves: Vec<[u8; SIZE]> = (0..5_u8).map(|leaf| [leaf; SIZE]).collect();
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new().with_leaves(leaves).try_build::<sha3::Sha3_256>().unwrap();
+    let leaves_indexes = tree.get_leaves_indexes();
+    let (_, multiproof) = tree.generate_multiproof(&[leaves_indexes[1], leaves_indexes[3]]).unwrap();
+    let json = serde_json::to_string(&multiproof).unwrap();
+    assert_eq!(serde_json::from_str::<MultiProof<SIZE>>(&json).unwrap(), multiproof);
+    let unsorted_json = json.replacen(&alloc::format!("[{},{}]", leaves_indexes[1].get_index(), leaves_indexes[3].get_index()), &alloc::format!("[{},{}]", leaves_indexes[3].get_index(), leaves_indexes[1].get_index()), 1);
+    assert!(alloc::format!("{}", serde_json::from_str::<MultiProof<SIZE>>(&unsorted_json).unwrap_err()).contains("sorted"));
+    let duplicated_json = json.replacen(&alloc::format!("[{},", leaves_indexes[1].get_index()), &alloc::format!("[{},", leaves_indexes[3].get_index()), 1);
+    assert!(alloc::format!("{}", serde_json::from_str::<MultiProof<SIZE>>(&duplicated_json).unwrap_err()).contains("duplicates"));
+    let (_, range_proof) = tree.generate_range_proof(DepthOffset::from((3, 1)), DepthOffset::from((3, 3))).unwrap();
+    let json = serde_json::to_string(&range_proof).unwrap();
+    assert_eq!(serde_json::from_str::<RangeProof<SIZE>>(&json).unwrap(), range_proof);
+    let reversed_json = json.replacen(r#""start":[3,1],"end":[3,3]"#, r#""start":[3,3],"end":[3,1]"#, 1);
+    assert!(alloc::format!("{}", serde_json::from_str::<RangeProof<SIZE>>(&reversed_json).unwrap_err()).contains("not before it"));
+    let shallow_json = json.replacen(r#""end":[3,3]"#, r#""end":[2,3]"#, 1);
+    assert!(alloc::format!("{}", serde_json::from_str::<RangeProof<SIZE>>(&shallow_json).unwrap_err()).contains("depth of its start"));
+}
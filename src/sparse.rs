@@ -0,0 +1,306 @@
+use core::fmt;
+use core::marker::PhantomData;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use crate::{
+    crypto::hash_visit,
+    error::LeMerkBuilderError,
+    traits::MerkleHasher,
+};
+
+/// Length in bytes of the keys of a SparseMerkleTree.
+pub const SPARSE_KEY_SIZE: usize = 32;
+/// Depth of the leaves of a SparseMerkleTree, one level per key bit.
+pub const SPARSE_DEPTH: usize = SPARSE_KEY_SIZE * 8;
+
+/// Sparse Merkle tree over the 256-bit key space, where the key bits are the path from the root to the leaf,
+/// most significant bit first, 0 for the left successor and 1 for the right one.
+/// Absent keys hold the empty leaf, an all zero block, so every subtree without keys has a default hash per depth.
+/// Only the nodes that differ from their default are stored, setting a key to the empty leaf removes it.
+pub struct SparseMerkleTree<const CIPHER_BLOCK_SIZE: usize, H = sha3::Sha3_256> {
+    /// Non default nodes by depth and path, i.e. the key bits up to the depth, the following ones set to zero.
+    nodes: BTreeMap<(usize, [u8; SPARSE_KEY_SIZE]), [u8; CIPHER_BLOCK_SIZE]>,
+    /// Root of an empty subtree, by depth from the root to the leaves.
+    default_hashes: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+    hasher: PhantomData<H>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> PartialEq for SparseMerkleTree<CIPHER_BLOCK_SIZE, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes == other.nodes
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> fmt::Debug for SparseMerkleTree<CIPHER_BLOCK_SIZE, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SparseMerkleTree")
+            .field("nodes", &self.nodes)
+            .finish()
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> Clone for SparseMerkleTree<CIPHER_BLOCK_SIZE, H> {
+    fn clone(&self) -> Self {
+        SparseMerkleTree {
+            nodes: self.nodes.clone(),
+            default_hashes: self.default_hashes.clone(),
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher> SparseMerkleTree<CIPHER_BLOCK_SIZE, H> {
+    /// Creates an empty tree, precomputing the default hashes.
+    pub fn try_new() -> Result<Self, LeMerkBuilderError> {
+        if H::OUTPUT_SIZE != CIPHER_BLOCK_SIZE { return Err(LeMerkBuilderError::HasherOutputSizeMismatch { expected: CIPHER_BLOCK_SIZE, found: H::OUTPUT_SIZE }); };
+        Ok(SparseMerkleTree {
+            nodes: BTreeMap::new(),
+            default_hashes: default_hashes::<CIPHER_BLOCK_SIZE, H>(),
+            hasher: PhantomData,
+        })
+    }
+    pub fn get_root_data(&self) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.get_node(0, &[0_u8; SPARSE_KEY_SIZE])
+    }
+    /// Number of keys holding a leaf.
+    pub fn len(&self) -> usize {
+        self.nodes.range((SPARSE_DEPTH, [0_u8; SPARSE_KEY_SIZE])..).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    /// Gets the leaf of a key, None if the key is absent.
+    pub fn get(&self, key: &[u8; SPARSE_KEY_SIZE]) -> Option<[u8; CIPHER_BLOCK_SIZE]> {
+        self.nodes.get(&(SPARSE_DEPTH, *key)).copied()
+    }
+    /// This method sets the leaf of a key and returns the root update.
+    pub fn insert(&mut self, key: &[u8; SPARSE_KEY_SIZE], leaf: [u8; CIPHER_BLOCK_SIZE]) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.set_node(SPARSE_DEPTH, *key, leaf);
+        let mut node = leaf;
+        for depth in (0..SPARSE_DEPTH).rev() {
+            let sibling = self.get_node(depth + 1, &sibling_path(key, depth + 1));
+            let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+            if key_bit(key, depth) {
+                hash_visit::<H, _>(&sibling, &node, &mut output);
+            } else {
+                hash_visit::<H, _>(&node, &sibling, &mut output);
+            };
+            node = output;
+            self.set_node(depth, path(key, depth), node);
+        }
+        node
+    }
+    /// This method removes the leaf of a key and returns the root update.
+    pub fn remove(&mut self, key: &[u8; SPARSE_KEY_SIZE]) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.insert(key, self.default_hashes[SPARSE_DEPTH])
+    }
+    /// Generates a proof of membership of a key, or of non membership if the key is absent.
+    /// Default siblings are left out of the proof and flagged in its bitmap.
+    pub fn generate_proof(&self, key: &[u8; SPARSE_KEY_SIZE]) -> SparseMerkleProof<CIPHER_BLOCK_SIZE> {
+        let mut bitmap = [0_u8; SPARSE_KEY_SIZE];
+        let mut siblings = Vec::new();
+        for depth in (0..SPARSE_DEPTH).rev() {
+            if let Some(sibling) = self.nodes.get(&(depth + 1, sibling_path(key, depth + 1))) {
+                bitmap[depth / 8] |= 0x80 >> (depth % 8);
+                siblings.push(*sibling);
+            };
+        }
+        SparseMerkleProof { key: *key, bitmap, siblings }
+    }
+    fn get_node(&self, depth: usize, path: &[u8; SPARSE_KEY_SIZE]) -> [u8; CIPHER_BLOCK_SIZE] {
+        self.nodes.get(&(depth, *path)).copied().unwrap_or(self.default_hashes[depth])
+    }
+    fn set_node(&mut self, depth: usize, path: [u8; SPARSE_KEY_SIZE], node: [u8; CIPHER_BLOCK_SIZE]) {
+        if node == self.default_hashes[depth] {
+            self.nodes.remove(&(depth, path));
+        } else {
+            self.nodes.insert((depth, path), node);
+        };
+    }
+}
+
+/// Proof of membership, or non membership, of a key in a SparseMerkleTree.
+/// Bit d of the bitmap, most significant bit first, is set when the sibling of the path at depth d + 1 isn't a default hash.
+/// Only those siblings are carried, ordered from the leaf level to the root level.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct SparseMerkleProof<const CIPHER_BLOCK_SIZE: usize> {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::block"))]
+    key: [u8; SPARSE_KEY_SIZE],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::block"))]
+    bitmap: [u8; SPARSE_KEY_SIZE],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::blocks"))]
+    siblings: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize> SparseMerkleProof<CIPHER_BLOCK_SIZE> {
+    pub fn new(key: [u8; SPARSE_KEY_SIZE], bitmap: [u8; SPARSE_KEY_SIZE], siblings: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> Self {
+        SparseMerkleProof { key, bitmap, siblings }
+    }
+    pub fn get_key(&self) -> [u8; SPARSE_KEY_SIZE] {
+        self.key
+    }
+    pub fn get_bitmap(&self) -> [u8; SPARSE_KEY_SIZE] {
+        self.bitmap
+    }
+    pub fn get_siblings(&self) -> &[[u8; CIPHER_BLOCK_SIZE]] {
+        &self.siblings
+    }
+    /// Rebuilds the root from the leaf of the key, the empty leaf for an absent key, without the tree.
+    /// Returns None if the siblings don't match the bitmap.
+    pub fn compute_root<H: MerkleHasher>(&self, leaf: &[u8; CIPHER_BLOCK_SIZE]) -> Option<[u8; CIPHER_BLOCK_SIZE]> {
+        let default_hashes = default_hashes::<CIPHER_BLOCK_SIZE, H>();
+        let mut siblings = self.siblings.iter();
+        let mut node = *leaf;
+        for depth in (0..SPARSE_DEPTH).rev() {
+            let sibling = if key_bit(&self.bitmap, depth) { *siblings.next()? } else { default_hashes[depth + 1] };
+            let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+            if key_bit(&self.key, depth) {
+                hash_visit::<H, _>(&sibling, &node, &mut output);
+            } else {
+                hash_visit::<H, _>(&node, &sibling, &mut output);
+            };
+            node = output;
+        }
+        if siblings.next().is_some() {
+            return None; // Unused siblings.
+        };
+        Some(node)
+    }
+    /// Verifies that the key holds the leaf in the tree of a known root. A proof for another key is rejected.
+    pub fn verify_membership<H: MerkleHasher>(&self, root: &[u8; CIPHER_BLOCK_SIZE], key: &[u8; SPARSE_KEY_SIZE], leaf: &[u8; CIPHER_BLOCK_SIZE]) -> bool {
+        key == &self.key && leaf != &[0_u8; CIPHER_BLOCK_SIZE] && self.compute_root::<H>(leaf).as_ref() == Some(root)
+    }
+    /// Verifies that the key is absent from the tree of a known root. A proof for another key is rejected.
+    pub fn verify_non_membership<H: MerkleHasher>(&self, root: &[u8; CIPHER_BLOCK_SIZE], key: &[u8; SPARSE_KEY_SIZE]) -> bool {
+        key == &self.key && self.compute_root::<H>(&[0_u8; CIPHER_BLOCK_SIZE]).as_ref() == Some(root)
+    }
+}
+
+/// Default hashes by depth, from the root of an empty tree to the empty leaf.
+fn default_hashes<const CIPHER_BLOCK_SIZE: usize, H: MerkleHasher>() -> Vec<[u8; CIPHER_BLOCK_SIZE]> {
+    let mut default_hashes = alloc::vec![[0_u8; CIPHER_BLOCK_SIZE]; SPARSE_DEPTH + 1];
+    for depth in (0..SPARSE_DEPTH).rev() {
+        let mut output = [0_u8; CIPHER_BLOCK_SIZE];
+        hash_visit::<H, _>(&default_hashes[depth + 1], &default_hashes[depth + 1], &mut output);
+        default_hashes[depth] = output;
+    }
+    default_hashes
+}
+
+/// Bit of the key choosing the successor of the path at depth, true for the right one.
+fn key_bit(key: &[u8; SPARSE_KEY_SIZE], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// Path of the node of depth on the way to the key, i.e. its first depth bits.
+fn path(key: &[u8; SPARSE_KEY_SIZE], depth: usize) -> [u8; SPARSE_KEY_SIZE] {
+    let mut path = [0_u8; SPARSE_KEY_SIZE];
+    path[..depth / 8].copy_from_slice(&key[..depth / 8]);
+    if !depth.is_multiple_of(8) {
+        path[depth / 8] = key[depth / 8] & (0xff_u8 << (8 - depth % 8));
+    };
+    path
+}
+
+/// Path of the pair to ancestor of the node of depth on the way to the key.
+fn sibling_path(key: &[u8; SPARSE_KEY_SIZE], depth: usize) -> [u8; SPARSE_KEY_SIZE] {
+    let mut path = path(key, depth);
+    path[(depth - 1) / 8] ^= 0x80 >> ((depth - 1) % 8);
+    path
+}
+
+#[cfg(test)]
+fn sparse_key(seed: u8) -> [u8; SPARSE_KEY_SIZE] {
+    let mut key = [0_u8; SPARSE_KEY_SIZE];
+    crate::crypto::data_hash::<sha3::Sha3_256, _>(&[seed], &mut key);
+    key
+}
+
+#[test]
+fn empty_sparse_tree_root_is_the_default_root() {
+    let tree: SparseMerkleTree<32> = SparseMerkleTree::try_new().unwrap();
+    assert_eq!(tree.get_root_data(), default_hashes::<32, sha3::Sha3_256>()[0]);
+    assert!(tree.is_empty());
+    assert_eq!(tree.get(&sparse_key(0)), None);
+    assert!(matches!(SparseMerkleTree::<32, sha3::Sha3_512>::try_new(), Err(LeMerkBuilderError::HasherOutputSizeMismatch { expected: 32, found: 64 })));
+}
+
+#[test]
+fn sparse_tree_stores_non_default_nodes_only() {
+    let mut tree: SparseMerkleTree<32> = SparseMerkleTree::try_new().unwrap();
+    let empty_root = tree.get_root_data();
+    let root = tree.insert(&sparse_key(1), [1_u8; 32]);
+    assert_eq!(root, tree.get_root_data());
+    assert_eq!(tree.nodes.len(), SPARSE_DEPTH + 1);
+    assert_eq!(tree.get(&sparse_key(1)), Some([1_u8; 32]));
+    tree.insert(&sparse_key(2), [2_u8; 32]);
+    assert_eq!(tree.len(), 2);
+    assert!(tree.nodes.len() < 2 * (SPARSE_DEPTH + 1));
+    tree.remove(&sparse_key(1));
+    tree.remove(&sparse_key(2));
+    assert!(tree.is_empty());
+    assert_eq!(tree.get_root_data(), empty_root);
+}
+
+#[test]
+fn sparse_root_is_independent_of_insertion_order() {
+    let mut tree: SparseMerkleTree<32> = SparseMerkleTree::try_new().unwrap();
+    let mut reversed_tree: SparseMerkleTree<32> = SparseMerkleTree::try_new().unwrap();
+    for seed in 0..20 {
+        tree.insert(&sparse_key(seed), [seed + 1; 32]);
+        reversed_tree.insert(&sparse_key(19 - seed), [20 - seed; 32]);
+    }
+    assert_eq!(tree.get_root_data(), reversed_tree.get_root_data());
+    assert_eq!(tree, reversed_tree);
+}
+
+#[test]
+fn sparse_membership_and_non_membership_proofs() {
+    let mut tree: SparseMerkleTree<32> = SparseMerkleTree::try_new().unwrap();
+    for seed in 0..10 {
+        tree.insert(&sparse_key(seed), [seed + 1; 32]);
+    }
+    let root = tree.get_root_data();
+    for seed in 0..10 {
+        let proof = tree.generate_proof(&sparse_key(seed));
+        assert!(proof.verify_membership::<sha3::Sha3_256>(&root, &sparse_key(seed), &[seed + 1; 32]));
+        assert!(!proof.verify_membership::<sha3::Sha3_256>(&root, &sparse_key(seed), &[seed + 2; 32]));
+        assert!(!proof.verify_non_membership::<sha3::Sha3_256>(&root, &sparse_key(seed)));
+    }
+    let absent_proof = tree.generate_proof(&sparse_key(10));
+    assert!(absent_proof.get_siblings().len() < 16);
+    assert!(absent_proof.verify_non_membership::<sha3::Sha3_256>(&root, &sparse_key(10)));
+    assert!(!absent_proof.verify_membership::<sha3::Sha3_256>(&root, &sparse_key(10), &[11_u8; 32]));
+    let mut truncated_siblings = absent_proof.get_siblings().to_vec();
+    truncated_siblings.pop();
+    let truncated_proof = SparseMerkleProof::new(absent_proof.get_key(), absent_proof.get_bitmap(), truncated_siblings);
+    assert_eq!(truncated_proof.compute_root::<sha3::Sha3_256>(&[0_u8; 32]), None);
+}
+
+#[test]
+fn sparse_proofs_for_another_key_are_rejected() {
+    let mut tree: SparseMerkleTree<32> = SparseMerkleTree::try_new().unwrap();
+    tree.insert(&sparse_key(0), [1_u8; 32]);
+    tree.insert(&sparse_key(1), [1_u8; 32]);
+    let root = tree.get_root_data();
+    // A valid proof that key 2 is absent doesn't prove key 0 absent.
+    let absent_proof = tree.generate_proof(&sparse_key(2));
+    assert!(absent_proof.verify_non_membership::<sha3::Sha3_256>(&root, &sparse_key(2)));
+    assert!(!absent_proof.verify_non_membership::<sha3::Sha3_256>(&root, &sparse_key(0)));
+    // A valid proof that key 1 holds a leaf doesn't prove key 0 holds it.
+    let member_proof = tree.generate_proof(&sparse_key(1));
+    assert!(member_proof.verify_membership::<sha3::Sha3_256>(&root, &sparse_key(1), &[1_u8; 32]));
+    assert!(!member_proof.verify_membership::<sha3::Sha3_256>(&root, &sparse_key(0), &[1_u8; 32]));
+}
+
+#[test]
+fn sparse_paths_follow_key_bits() {
+    let key = [0b1010_0000_u8; SPARSE_KEY_SIZE];
+    assert!(key_bit(&key, 0) && !key_bit(&key, 1) && key_bit(&key, 2));
+    assert_eq!(path(&key, 3)[0], 0b1010_0000);
+    assert_eq!(path(&key, 1)[0], 0b1000_0000);
+    assert_eq!(path(&key, 9)[..2], [0b1010_0000, 0b1000_0000]);
+    assert_eq!(sibling_path(&key, 3)[0], 0b1000_0000);
+    assert_eq!(sibling_path(&key, SPARSE_DEPTH)[SPARSE_KEY_SIZE - 1], 0b1010_0001);
+}
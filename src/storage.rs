@@ -0,0 +1,322 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use crate::{
+    LeMerkLevel,
+    data::Index,
+    error::LeMerkLevelError,
+    traits::NodeStore,
+};
+#[cfg(test)]
+use hex_literal::hex;
+#[cfg(test)]
+use crate::{
+    LeMerkTree,
+    builder::LeMerkBuilder,
+    data::{
+        DepthOffset,
+        OddNodePolicy,
+    },
+};
+
+/// Memory layout for a whole flat hash tree holding a default block per depth, and only the blocks that were written.
+/// Untouched positions read as the default of their depth, so a uniformly filled tree costs memory proportional to its updates.
+/// Blocks are indexed as in the flat hash tree: leaves first, the root last.
+pub struct LazyLevel<const CIPHER_BLOCK_SIZE: usize, H = sha3::Sha3_256> {
+    /// Level's length of the Merkle Tree.
+    max_depth: usize,
+    /// Default block by depth, from the root to the leaves.
+    defaults: Vec<[u8; CIPHER_BLOCK_SIZE]>,
+    /// Written blocks by flat hash tree index.
+    blocks: BTreeMap<usize, [u8; CIPHER_BLOCK_SIZE]>,
+    hasher: PhantomData<H>,
+}
+
+/// Layouts are equal when they read the same blocks, whichever of them were written. They are compared depth by depth:
+/// only the written blocks are read, and a depth with different defaults must be written over in one of them.
+impl<const CIPHER_BLOCK_SIZE: usize, H> PartialEq for LazyLevel<CIPHER_BLOCK_SIZE, H> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.max_depth != other.max_depth { return false; };
+        (0..=self.max_depth).all(|depth| {
+            let range = self.get_depth_range(depth);
+            let mut indexes = self.blocks
+                .range(range.clone())
+                .chain(other.blocks.range(range.clone()).filter(|(index, _)| !self.blocks.contains_key(index)))
+                .map(|(index, _)| Index::from(*index));
+            (self.defaults[depth] == other.defaults[depth] || indexes.clone().count() == range.len())
+                && indexes.all(|index| self.get_cipher_block(index) == other.get_cipher_block(index))
+        })
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> fmt::Debug for LazyLevel<CIPHER_BLOCK_SIZE, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyLevel")
+            .field("max_depth", &self.max_depth)
+            .field("defaults", &self.defaults)
+            .field("blocks", &self.blocks)
+            .finish()
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> Clone for LazyLevel<CIPHER_BLOCK_SIZE, H> {
+    fn clone(&self) -> Self {
+        LazyLevel {
+            max_depth: self.max_depth,
+            defaults: self.defaults.clone(),
+            blocks: self.blocks.clone(),
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> LazyLevel<CIPHER_BLOCK_SIZE, H> {
+    /// Creates a layout with no written block, from the default blocks by depth, from the root to the leaves.
+    /// There must be between 1 and usize::BITS defaults, so the flat hash tree length fits in usize.
+    pub fn try_new(defaults: Vec<[u8; CIPHER_BLOCK_SIZE]>) -> Result<Self, LeMerkLevelError> {
+        if defaults.is_empty() || defaults.len() > usize::BITS as usize {
+            return Err(LeMerkLevelError::BadDefaultCount(defaults.len()));
+        };
+        Ok(LazyLevel {
+            max_depth: defaults.len() - 1,
+            defaults,
+            blocks: BTreeMap::new(),
+            hasher: PhantomData,
+        })
+    }
+    pub fn get_cipher_block(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError> {
+        let default = self.get_default(index)?;
+        Ok(self.blocks.get(&index.get_index()).copied().unwrap_or(default))
+    }
+    /// Gets a mutable reference to a block, writing its default first if it's untouched.
+    pub fn get_cipher_block_mut_ref(&mut self, index: Index) -> Result<&mut [u8; CIPHER_BLOCK_SIZE], LeMerkLevelError> {
+        let default = self.get_default(index)?;
+        Ok(self.blocks.entry(index.get_index()).or_insert(default))
+    }
+    /// Length of the flat hash tree, written or not.
+    pub fn len(&self) -> usize {
+        usize::MAX >> (usize::BITS as usize - 1 - self.max_depth)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Number of blocks that were written.
+    pub fn get_written_length(&self) -> usize {
+        self.blocks.len()
+    }
+    /// Flat hash tree indexes of a depth, see get_default.
+    fn get_depth_range(&self, depth: usize) -> Range<usize> {
+        let length = self.len();
+        length - (usize::MAX >> (usize::BITS as usize - 1 - depth))..length - ((1 << depth) - 1)
+    }
+    /// Compares the layout to the blocks of a whole flat hash tree, depth by depth. The written blocks must match,
+    /// and the blocks differing from the default of their depth must all be written ones.
+    fn eq_blocks(&self, blocks: &[[u8; CIPHER_BLOCK_SIZE]]) -> bool {
+        blocks.len() == self.len() && (0..=self.max_depth).all(|depth| {
+            let range = self.get_depth_range(depth);
+            let default = self.defaults[depth];
+            let written = self.blocks.range(range.clone());
+            written.clone().all(|(index, block)| blocks[*index] == *block)
+                && blocks[range].iter().filter(|block| **block != default).count() == written.filter(|(_, block)| **block != default).count()
+        })
+    }
+    /// Default block of the depth of a flat hash tree index. The depth k holds the indexes from 2^(d+1) - 2^(k+1) to 2^(d+1) - 2^k,
+    /// so the distance of an index to the end of the flat hash tree has k as its logarithm.
+    fn get_default(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError> {
+        let index_usize = index.get_index();
+        if index_usize >= self.len() { return Err(LeMerkLevelError::Overflow { index: index_usize, length: self.len() }); };
+        let depth = (self.len() - index_usize).ilog2() as usize;
+        Ok(self.defaults[depth])
+    }
+}
+
+/// Storage of the flat hash tree of a LeMerkTree: every block in a LeMerkLevel, or only the written ones in a LazyLevel.
+pub enum FlatHashTree<const CIPHER_BLOCK_SIZE: usize, H = sha3::Sha3_256> {
+    Dense(LeMerkLevel<CIPHER_BLOCK_SIZE, H>),
+    Lazy(LazyLevel<CIPHER_BLOCK_SIZE, H>),
+}
+
+/// A dense and a lazy storage are equal when they hold the same blocks.
+impl<const CIPHER_BLOCK_SIZE: usize, H> PartialEq for FlatHashTree<CIPHER_BLOCK_SIZE, H> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FlatHashTree::Dense(level), FlatHashTree::Dense(other_level)) => level == other_level,
+            (FlatHashTree::Lazy(level), FlatHashTree::Lazy(other_level)) => level == other_level,
+            (FlatHashTree::Dense(level), FlatHashTree::Lazy(other_level)) => other_level.eq_blocks(&level.0),
+            (FlatHashTree::Lazy(level), FlatHashTree::Dense(other_level)) => level.eq_blocks(&other_level.0),
+        }
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> fmt::Debug for FlatHashTree<CIPHER_BLOCK_SIZE, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlatHashTree::Dense(level) => level.fmt(f),
+            FlatHashTree::Lazy(level) => level.fmt(f),
+        }
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> Clone for FlatHashTree<CIPHER_BLOCK_SIZE, H> {
+    fn clone(&self) -> Self {
+        match self {
+            FlatHashTree::Dense(level) => FlatHashTree::Dense(level.clone()),
+            FlatHashTree::Lazy(level) => FlatHashTree::Lazy(level.clone()),
+        }
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> NodeStore<CIPHER_BLOCK_SIZE> for LazyLevel<CIPHER_BLOCK_SIZE, H> {
+    fn len(&self) -> usize {
+        LazyLevel::len(self)
+    }
+    fn get(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError> {
+        self.get_cipher_block(index)
+    }
+    fn put(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<(), LeMerkLevelError> {
+        *self.get_cipher_block_mut_ref(index)? = block;
+        Ok(())
+    }
+}
+
+impl<const CIPHER_BLOCK_SIZE: usize, H> NodeStore<CIPHER_BLOCK_SIZE> for FlatHashTree<CIPHER_BLOCK_SIZE, H> {
+    fn len(&self) -> usize {
+        match self {
+            FlatHashTree::Dense(level) => NodeStore::len(level),
+            FlatHashTree::Lazy(level) => NodeStore::len(level),
+        }
+    }
+    fn get(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError> {
+        match self {
+            FlatHashTree::Dense(level) => level.get(index),
+            FlatHashTree::Lazy(level) => level.get(index),
+        }
+    }
+    fn put(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<(), LeMerkLevelError> {
+        match self {
+            FlatHashTree::Dense(level) => level.put(index, block),
+            FlatHashTree::Lazy(level) => level.put(index, block),
+        }
+    }
+    fn get_range(&self, start: usize, end: usize) -> Result<Vec<[u8; CIPHER_BLOCK_SIZE]>, LeMerkLevelError> {
+        match self {
+            FlatHashTree::Dense(level) => level.get_range(start, end),
+            FlatHashTree::Lazy(level) => level.get_range(start, end),
+        }
+    }
+}
+
+#[test]
+fn lazy_level_falls_back_to_depth_defaults() {
+    let mut level: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[0], [1], [2]]).unwrap();
+    assert_eq!(level.len(), 7);
+    let blocks: Vec<[u8; 1]> = (0..7).map(|index| level.get_cipher_block(Index::from(index)).unwrap()).collect();
+    assert_eq!(blocks, [[2], [2], [2], [2], [1], [1], [0]]);
+    assert_eq!(level.get_cipher_block(Index::from(7)), Err(LeMerkLevelError::Overflow { index: 7, length: 7 }));
+    *level.get_cipher_block_mut_ref(Index::from(5)).unwrap() = [9];
+    assert_eq!(level.get_cipher_block(Index::from(5)).unwrap(), [9]);
+    assert_eq!(level.get_cipher_block(Index::from(4)).unwrap(), [1]);
+    assert_eq!(level.get_written_length(), 1);
+}
+
+#[test]
+fn storages_holding_the_same_blocks_are_equal() {
+    let mut level: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[0], [1], [2]]).unwrap();
+    level.put(Index::from(5), [1]).unwrap(); // Writes the default of depth 1.
+    assert_eq!(level, LazyLevel::try_new(alloc::vec![[0], [1], [2]]).unwrap());
+    level.put(Index::from(5), [9]).unwrap();
+    assert_ne!(level, LazyLevel::try_new(alloc::vec![[0], [1], [2]]).unwrap());
+    let dense = LeMerkLevel::from(alloc::vec![[2], [2], [2], [2], [1], [9], [0]]);
+    assert_eq!(FlatHashTree::Lazy(level.clone()), FlatHashTree::Dense(dense));
+    let mut zeros: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[0], [0], [0]]).unwrap();
+    for (index, block) in [[2], [2], [2], [2], [1], [9]].into_iter().enumerate() {
+        zeros.put(Index::from(index), block).unwrap();
+    }
+    assert_eq!(zeros, level);
+}
+
+#[test]
+fn lazy_level_needs_a_default_per_depth() {
+    assert_eq!(LazyLevel::<1>::try_new(alloc::vec![]).unwrap_err(), LeMerkLevelError::BadDefaultCount(0));
+    let count = usize::BITS as usize + 1;
+    assert_eq!(LazyLevel::<1>::try_new(alloc::vec![[0]; count]).unwrap_err(), LeMerkLevelError::BadDefaultCount(count));
+    let root: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[3]]).unwrap();
+    assert_eq!((root.len(), root.is_empty()), (1, false));
+    assert_eq!(root.get_cipher_block(Index::from(0)).unwrap(), [3]);
+    assert_eq!(root.get_cipher_block(Index::from(1)), Err(LeMerkLevelError::Overflow { index: 1, length: 1 }));
+    let widest: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[0]; usize::BITS as usize]).unwrap();
+    assert_eq!(widest.len(), usize::MAX);
+    assert_eq!(widest.get_cipher_block(Index::from(usize::MAX - 1)).unwrap(), [0]);
+}
+
+#[test]
+fn lazy_levels_are_compared_by_depth() {
+    let mut level: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[0]; 61]).unwrap();
+    let mut other: LazyLevel<1> = LazyLevel::try_new([[0]; 60].into_iter().chain([[1]]).collect()).unwrap();
+    assert_ne!(level, other); // The leaves read different defaults, without going through them.
+    level.put(Index::from(0), [1]).unwrap();
+    other.put(Index::from(1), [0]).unwrap();
+    assert_ne!(level, other);
+    let mut root: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[0], [1]]).unwrap();
+    let mut other_root: LazyLevel<1> = LazyLevel::try_new(alloc::vec![[0], [2]]).unwrap();
+    root.put(Index::from(0), [2]).unwrap();
+    assert_ne!(root, other_root);
+    other_root.put(Index::from(1), [1]).unwrap();
+    assert_eq!(root, other_root); // Each leaf is written over in one of them.
+    assert_eq!(FlatHashTree::Lazy(root.clone()), FlatHashTree::Dense(LeMerkLevel::from(alloc::vec![[2], [1], [0]])));
+    assert_ne!(FlatHashTree::Dense(LeMerkLevel::from(alloc::vec![[2], [2], [0]])), FlatHashTree::Lazy(root));
+}
+
+#[test]
+fn lazy_storage_matches_dense_storage() {
+    const SIZE: usize = 32;
+    for leaf_count in [5, 16] {
+        let builder = LeMerkBuilder::<SIZE>::new()
+            .with_leaf_count(leaf_count)
+            .with_initial_block(hex!("abababababababababababababababababababababababababababababababab"));
+        let mut tree: LeMerkTree<SIZE> = builder.try_build::<sha3::Sha3_256>().unwrap();
+        let mut lazy_tree: LeMerkTree<SIZE> = builder.with_lazy_storage(true).try_build::<sha3::Sha3_256>().unwrap();
+        assert_eq!(lazy_tree.get_root_data().unwrap(), tree.get_root_data().unwrap());
+        // Nodes not covering any leaf read as zeros in both storages.
+        assert_eq!(lazy_tree.encode().unwrap(), tree.encode().unwrap());
+        assert_eq!(lazy_tree, tree);
+        for leaf_index in tree.get_leaves_indexes().into_iter().step_by(3) {
+            let block = [leaf_index.get_index() as u8; SIZE];
+            assert_eq!(lazy_tree.set_update_generate_proof(leaf_index, block).unwrap(), tree.set_update_generate_proof(leaf_index, block).unwrap());
+        }
+        for leaf_index in tree.get_leaves_indexes() {
+            assert_eq!(lazy_tree.generate_proof(leaf_index).unwrap(), tree.generate_proof(leaf_index).unwrap());
+        }
+        assert_eq!(lazy_tree.get_level_by_depth_index(2).unwrap(), tree.get_level_by_depth_index(2).unwrap());
+        assert_eq!(lazy_tree, tree);
+    }
+}
+
+#[test]
+fn lazy_storage_keeps_only_updated_blocks() {
+    const SIZE: usize = 32;
+    let tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_depth_length(20)
+        .with_initial_block(hex!("abababababababababababababababababababababababababababababababab"))
+        .with_lazy_storage(true)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    assert_eq!(tree.get_root_data().unwrap(), hex!("d4490f4d374ca8a44685fe9471c5b8dbe58cdffd13d30d9aba15dd29efb92930"));
+    let mut deep_tree: LeMerkTree<SIZE> = LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(30)
+        .with_lazy_storage(true)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    for offset in [0, 1, 1 << 29] {
+        let leaf_index = Index::try_from(DepthOffset::from((30, offset))).unwrap();
+        let (root, proof) = deep_tree.set_update_generate_proof(leaf_index, [1_u8; SIZE]).unwrap();
+        assert!(proof.verify::<sha3::Sha3_256>(&root, &[1_u8; SIZE], leaf_index, 1 << 30, OddNodePolicy::Promote).unwrap());
+    }
+    // Leaves 0 and 1 share 30 ancestors, the last leaf shares the root only.
+    match &deep_tree.flat_hash_tree {
+        FlatHashTree::Lazy(level) => assert_eq!(level.get_written_length(), 2 + 30 + 1 + 29),
+        FlatHashTree::Dense(_) => panic!("Expected a lazy storage."),
+    }
+}
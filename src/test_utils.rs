@@ -0,0 +1,43 @@
+use alloc::vec::Vec;
+use crate::{
+    LeMerkTree,
+    builder::LeMerkBuilder,
+    crypto::data_hash,
+    data::OddNodePolicy,
+};
+
+/// Every odd node policy, for the tests covering all of them.
+pub(crate) const ODD_NODE_POLICIES: [OddNodePolicy; 3] = [OddNodePolicy::Promote, OddNodePolicy::Duplicate, OddNodePolicy::Rfc6962];
+
+/// Tree of leaf_count distinct leaves, the hashes of their offsets, set after the build. It returns the tree and its leaves.
+pub(crate) fn distinct_leaves_tree(leaf_count: usize, odd_node_policy: OddNodePolicy) -> (LeMerkTree<32>, Vec<[u8; 32]>) {
+    let mut tree: LeMerkTree<32> = LeMerkBuilder::<32>::new()
+        .with_leaf_count(leaf_count)
+        .with_odd_node_policy(odd_node_policy)
+        .try_build::<sha3::Sha3_256>()
+        .expect("Unexpected build.");
+    let leaves: Vec<[u8; 32]> = (0..leaf_count)
+        .map(|offset| {
+            let mut leaf = [0_u8; 32];
+            data_hash::<sha3::Sha3_256, _>(&offset.to_le_bytes(), &mut leaf);
+            leaf
+        })
+        .collect();
+    for (leaf_index, leaf) in tree.get_leaves_indexes().into_iter().zip(leaves.iter()) {
+        tree.set_and_update(leaf_index, *leaf).unwrap();
+    }
+    (tree, leaves)
+}
+
+/// Distinct leaves trees of every odd node policy and leaf count, with their policy.
+pub(crate) fn distinct_leaves_trees<I>(leaf_counts: I) -> impl Iterator<Item = (OddNodePolicy, LeMerkTree<32>, Vec<[u8; 32]>)>
+where
+    I: IntoIterator<Item = usize> + Clone,
+{
+    ODD_NODE_POLICIES.into_iter().flat_map(move |odd_node_policy| {
+        leaf_counts.clone().into_iter().map(move |leaf_count| {
+            let (tree, leaves) = distinct_leaves_tree(leaf_count, odd_node_policy);
+            (odd_node_policy, tree, leaves)
+        })
+    })
+}
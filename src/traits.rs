@@ -1,5 +1,92 @@
+use alloc::vec::Vec;
+use crate::{
+    data::Index,
+    error::LeMerkLevelError,
+    format::TreeSection,
+};
+#[cfg(test)]
+use crate::{
+    LeMerkTree,
+    builder::LeMerkBuilder,
+};
+
 /// SizedTree trait is used by VirtualNode implementation to get the properties of the tree that is being assessed against, without the need to nest it to a LeMerkTree.
 pub trait SizedTree {
     fn get_max_index(&self) -> usize;
     fn get_max_depth(&self) -> usize;
-}
\ No newline at end of file
+}
+
+/// MerkleHasher trait is the hash function abstraction used by LeMerkTree, LeMerkLevel, LeMerkBuilder and the proofs.
+/// It can be implemented for any hash function, not only for the RustCrypto Digest family,
+/// as long as its output is exactly the CIPHER_BLOCK_SIZE of the tree it is used with.
+pub trait MerkleHasher {
+    /// Length in bytes of the hasher output.
+    const OUTPUT_SIZE: usize;
+    /// Identifier of the hasher in persisted trees, so a tree isn't reopened with another hasher.
+    /// The built-in hashers use identifiers up to 0x1ff, custom hashers must pick one of their own above it.
+    const HASHER_ID: u32;
+    /// Whether hash_leaf never matches hash_node, as with the RFC 6962 prefixes. Payloads of the other hashers are hashed twice,
+    /// see crypto::payload_hash.
+    const LEAF_DOMAIN_SEPARATION: bool = false;
+    /// Hashes arbitrary data into a leaf block, copying the result to output.
+    /// An output of another length than OUTPUT_SIZE must be left untouched rather than panicked on,
+    /// crypto::data_hash and crypto::hash_visit check it for their callers.
+    fn hash_leaf(data: &[u8], output: &mut [u8]);
+    /// Hashes a pair of sibling blocks into their ancestor block, copying the result to output.
+    fn hash_node(left: &[u8], right: &[u8], output: &mut [u8]);
+}
+
+/// NodeStore trait is the storage abstraction of the flat hash tree of a LeMerkTree.
+/// Blocks are indexed by flat hash tree index: the leaves first, the root last.
+/// It can be implemented for arenas, files or key-value stores, LeMerkLevel being the in-memory one.
+pub trait NodeStore<const CIPHER_BLOCK_SIZE: usize> {
+    /// Number of blocks of the flat hash tree.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Gets a block by its flat hash tree index.
+    fn get(&self, index: Index) -> Result<[u8; CIPHER_BLOCK_SIZE], LeMerkLevelError>;
+    /// Sets a block by its flat hash tree index.
+    fn put(&mut self, index: Index, block: [u8; CIPHER_BLOCK_SIZE]) -> Result<(), LeMerkLevelError>;
+    /// Sets a batch of blocks by their flat hash tree index, in order.
+    fn put_batch(&mut self, blocks: &[(Index, [u8; CIPHER_BLOCK_SIZE])]) -> Result<(), LeMerkLevelError> {
+        blocks.iter().try_for_each(|(index, block)| self.put(*index, *block))
+    }
+    /// Copies the blocks from the start to the end flat hash tree index, excluded.
+    fn get_range(&self, start: usize, end: usize) -> Result<Vec<[u8; CIPHER_BLOCK_SIZE]>, LeMerkLevelError> {
+        (start..end).map(|index| self.get(Index::from(index))).collect()
+    }
+    /// Leaf count and odd node policy of the tree, for stores persisting them. A tree is only built into or restored from
+    /// a store whose tree section matches the builder.
+    fn get_tree_section(&self) -> Option<TreeSection> {
+        None
+    }
+}
+
+#[test]
+fn custom_merkle_hasher_tree() {
+    /// A toy, non cryptographic hasher that only checks the plumbing of the MerkleHasher trait.
+    struct XorHasher;
+    impl MerkleHasher for XorHasher {
+        const OUTPUT_SIZE: usize = 4;
+        const HASHER_ID: u32 = 0x1000;
+        fn hash_leaf(data: &[u8], output: &mut [u8]) {
+            output.fill(0);
+            data.iter().enumerate().for_each(|(i, byte)| output[i % 4] ^= byte);
+        }
+        fn hash_node(left: &[u8], right: &[u8], output: &mut [u8]) {
+            output.iter_mut().enumerate().for_each(|(i, byte)| *byte = left[i].rotate_left(1) ^ right[i]);
+        }
+    }
+    const SIZE: usize = 4;
+    let mut tree: LeMerkTree<SIZE, XorHasher> = LeMerkBuilder::<SIZE>::new()
+        .with_max_depth(1)
+        .with_initial_block([1, 2, 3, 4])
+        .try_build::<XorHasher>()
+        .expect("Unexpected build.");
+    assert_eq!(tree.get_root_data().unwrap(), [3, 6, 5, 12]);
+    let leaf_index = tree.get_leaves_indexes()[0];
+    assert_eq!(tree.set_and_update(leaf_index, [0, 0, 0, 0]).unwrap(), [1, 2, 3, 4]);
+    assert_eq!(tree.verify_path_to_root_by_index(leaf_index).unwrap().get_verified_root().unwrap(), [1, 2, 3, 4]);
+}